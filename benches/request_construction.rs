@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: MIT
+
+// The cost of cloning a Handle and of building requests, which each hold a clone
+// of it. Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use xfrmnetlink::new_connection;
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<24} {:>8.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let (connection, handle, _) = new_connection().unwrap();
+    tokio::spawn(connection);
+
+    let src = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    let dst = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(handle.clone());
+    }
+    report("Handle::clone", start.elapsed());
+
+    let start = Instant::now();
+    for spi in 0..ITERATIONS {
+        black_box(handle.state().add(src, dst).spi(spi));
+    }
+    report("StateHandle::add", start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(handle.policy().get_dump());
+    }
+    report("PolicyHandle::get_dump", start.elapsed());
+}
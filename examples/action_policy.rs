@@ -19,7 +19,7 @@ async fn main() -> Result<(), Error> {
     let cli_args = match policy_action_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             usage();
             std::process::exit(1);
         }
@@ -51,7 +51,7 @@ async fn set_default_policy_action(handle: Handle, ca: &PolicyActionCliArgs) ->
     let fwd_act = ca.fwd_action.unwrap_or(XFRM_USERPOLICY_UNSPEC);
     let out_act = ca.out_action.unwrap_or(XFRM_USERPOLICY_UNSPEC);
 
-    let _req = handle
+    handle
         .policy()
         .set_default_action(in_act, fwd_act, out_act)
         .execute()
@@ -19,7 +19,7 @@ async fn main() -> Result<(), Error> {
     let cli_args = match policy_add_upd_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     };
@@ -117,8 +117,8 @@ async fn add_upd_policy(handle: Handle, ca: &PolicyAddUpdCliArgs) -> Result<(),
         }
     }
 
-    let mut tmpls = ca.templates.iter();
-    while let Some(tmpl) = tmpls.next() {
+    let tmpls = ca.templates.iter();
+    for tmpl in tmpls {
         let mut ut = UserTemplate::default();
         ut.source(&tmpl.src_addr.addr());
         ut.destination(&tmpl.dst_addr.addr());
@@ -21,7 +21,7 @@ async fn main() -> Result<(), Error> {
     let cli_args = match state_add_upd_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     };
@@ -49,10 +49,7 @@ async fn main() -> Result<(), Error> {
             let payload = message.payload;
             if let NetlinkPayload::InnerMessage(xfrm_msg) = payload {
                 // Could match on XfrmMessage enum variants to print something unique
-                match xfrm_msg {
-                    //XfrmMessage::AddSa(_m) => {}
-                    _ => println!("XFRM event message - {:?}", xfrm_msg),
-                };
+                println!("XFRM event message - {:?}", xfrm_msg);
             } else {
                 println!("Other netlink message - {:?}", payload);
             }
@@ -89,27 +86,35 @@ fn add_upd_state(handle: Handle, ca: &StateAddUpdCliArgs) -> Result<(), Error> {
             .spi(ca.spi)
     };
 
-    if ca.enc_alg_name.is_some() {
-        if ca.enc_alg_key.len() > 0 {
-            req = req.encryption(ca.enc_alg_name.as_ref().unwrap(), &ca.enc_alg_key)?;
+    if let Some(enc_alg_name) = &ca.enc_alg_name {
+        if !ca.enc_alg_key.is_empty() {
+            req = req.encryption(enc_alg_name, &ca.enc_alg_key)?;
         }
-        if ca.auth_alg_name.is_some() && ca.auth_alg_key.len() > 0 {
-            req = req.authentication(ca.auth_alg_name.as_ref().unwrap(), &ca.auth_alg_key)?;
-        } else if ca.auth_trunc_alg_name.is_some() && ca.auth_trunc_alg_key.len() > 0 {
+        let auth_alg_name = ca
+            .auth_alg_name
+            .as_ref()
+            .filter(|_| !ca.auth_alg_key.is_empty());
+        let auth_trunc_alg_name = ca
+            .auth_trunc_alg_name
+            .as_ref()
+            .filter(|_| !ca.auth_trunc_alg_key.is_empty());
+        if let Some(auth_alg_name) = auth_alg_name {
+            req = req.auth_full(auth_alg_name, &ca.auth_alg_key)?;
+        } else if let Some(auth_trunc_alg_name) = auth_trunc_alg_name {
             req = req.authentication_trunc(
-                ca.auth_trunc_alg_name.as_ref().unwrap(),
+                auth_trunc_alg_name,
                 &ca.auth_trunc_alg_key,
                 ca.auth_trunc_len,
             )?;
         }
-    } else if ca.aead_alg_name.is_some() && ca.aead_alg_key.len() > 0 {
-        req = req.encryption_aead(
-            ca.aead_alg_name.as_ref().unwrap(),
-            &ca.aead_alg_key,
-            ca.aead_icv_len,
-        )?;
-    } else if ca.comp_alg_name.is_some() {
-        req = req.compression(ca.comp_alg_name.as_ref().unwrap())?;
+    } else if let Some(aead_alg_name) = ca
+        .aead_alg_name
+        .as_ref()
+        .filter(|_| !ca.aead_alg_key.is_empty())
+    {
+        req = req.encryption_aead(aead_alg_name, &ca.aead_alg_key, ca.aead_icv_len)?;
+    } else if let Some(comp_alg_name) = &ca.comp_alg_name {
+        req = req.compression(comp_alg_name)?;
     }
 
     if let Some(m) = ca.mode {
@@ -188,13 +193,9 @@ fn add_upd_state(handle: Handle, ca: &StateAddUpdCliArgs) -> Result<(), Error> {
     }
 
     if let Some(encap_proto) = ca.encap_type {
-        if ca.encap_sport.is_some() && ca.encap_dport.is_some() && ca.encap_oa.is_some() {
-            req = req.encapsulation(
-                encap_proto,
-                ca.encap_sport.unwrap(),
-                ca.encap_dport.unwrap(),
-                ca.encap_oa.unwrap().addr(),
-            );
+        if let (Some(sport), Some(dport), Some(oa)) = (ca.encap_sport, ca.encap_dport, ca.encap_oa)
+        {
+            req = req.encapsulation(encap_proto, sport, dport, oa.addr());
         }
     }
 
@@ -1,10 +1,12 @@
 // SPDX-License-Identifier: MIT
 
+// Each example only uses part of the parsed arguments.
+#![allow(dead_code)]
+
 use std::iter::Peekable;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 
-use hex;
 use ipnet::IpNet;
 use netlink_packet_xfrm::constants::*;
 use thiserror::Error;
@@ -147,7 +149,7 @@ fn parse_ipnet_arg(ip: &String) -> Result<IpNet, CliError> {
             }
         }
     }
-    return Ok(ipnet_addr);
+    Ok(ipnet_addr)
 }
 
 fn parse_proto_arg<'a>(
@@ -268,15 +270,11 @@ fn parse_hex_data(hex_str: &str) -> Result<Vec<u8>, CliError> {
     if hex_str.len() > 2 && hex_str.starts_with("0x") {
         pos = 2;
     }
-    return Ok(
-        hex::decode(&hex_str[pos..]).map_err(|_| CliError::InvalidHexArg(hex_str.to_string()))?
-    );
+    hex::decode(&hex_str[pos..]).map_err(|_| CliError::InvalidHexArg(hex_str.to_string()))
 }
 
 #[allow(dead_code)]
-pub(crate) fn policy_add_upd_parse_args(
-    args: &Vec<String>,
-) -> Result<PolicyAddUpdCliArgs, CliError> {
+pub(crate) fn policy_add_upd_parse_args(args: &[String]) -> Result<PolicyAddUpdCliArgs, CliError> {
     let mut update: bool = false;
     let mut src_addr: IpNet = IpNet::default();
     let mut dst_addr: IpNet = IpNet::default();
@@ -379,16 +377,13 @@ pub(crate) fn policy_add_upd_parse_args(
                         mark_and_mask = Some((mark_32, u32::MAX));
 
                         if let Some((_p, m)) = it.peek() {
-                            match m.as_str() {
-                                "mask" => {
-                                    it.next();
-                                    if let Some((_p, mask)) = it.next() {
-                                        if let Ok(mask_32) = mask.parse::<u32>() {
-                                            mark_and_mask = Some((mark_32, mask_32));
-                                        }
+                            if m.as_str() == "mask" {
+                                it.next();
+                                if let Some((_p, mask)) = it.next() {
+                                    if let Ok(mask_32) = mask.parse::<u32>() {
+                                        mark_and_mask = Some((mark_32, mask_32));
                                     }
                                 }
-                                _ => (),
                             }
                         }
                     }
@@ -685,9 +680,7 @@ pub struct PolicyDelGetCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn policy_del_get_parse_args(
-    args: &Vec<String>,
-) -> Result<PolicyDelGetCliArgs, CliError> {
+pub(crate) fn policy_del_get_parse_args(args: &[String]) -> Result<PolicyDelGetCliArgs, CliError> {
     let mut get: bool = false;
     let mut src_addr: IpNet = IpNet::default();
     let mut dst_addr: IpNet = IpNet::default();
@@ -767,16 +760,13 @@ pub(crate) fn policy_del_get_parse_args(
                         mark_and_mask = Some((mark_32, u32::MAX));
 
                         if let Some((_p, m)) = it.peek() {
-                            match m.as_str() {
-                                "mask" => {
-                                    it.next();
-                                    if let Some((_p, mask)) = it.next() {
-                                        if let Ok(mask_32) = mask.parse::<u32>() {
-                                            mark_and_mask = Some((mark_32, mask_32));
-                                        }
+                            if m.as_str() == "mask" {
+                                it.next();
+                                if let Some((_p, mask)) = it.next() {
+                                    if let Ok(mask_32) = mask.parse::<u32>() {
+                                        mark_and_mask = Some((mark_32, mask_32));
                                     }
                                 }
-                                _ => (),
                             }
                         }
                     }
@@ -836,7 +826,7 @@ pub struct PolicyFlushCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn policy_flush_parse_args(args: &Vec<String>) -> Result<PolicyFlushCliArgs, CliError> {
+pub(crate) fn policy_flush_parse_args(args: &[String]) -> Result<PolicyFlushCliArgs, CliError> {
     let mut ptype: Option<u8> = None;
 
     let mut it = args.iter().enumerate().peekable();
@@ -870,7 +860,7 @@ pub struct PolicySpdCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn policy_spd_parse_args(args: &Vec<String>) -> Result<PolicySpdCliArgs, CliError> {
+pub(crate) fn policy_spd_parse_args(args: &[String]) -> Result<PolicySpdCliArgs, CliError> {
     let mut hthresh4: Option<(u8, u8)> = None;
     let mut hthresh6: Option<(u8, u8)> = None;
 
@@ -935,9 +925,7 @@ pub struct PolicyActionCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn policy_action_parse_args(
-    args: &Vec<String>,
-) -> Result<PolicyActionCliArgs, CliError> {
+pub(crate) fn policy_action_parse_args(args: &[String]) -> Result<PolicyActionCliArgs, CliError> {
     let mut set_action: bool = false;
     let mut in_action: Option<u8> = None;
     let mut fwd_action: Option<u8> = None;
@@ -1054,7 +1042,7 @@ pub struct StateAddUpdCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn state_add_upd_parse_args(args: &Vec<String>) -> Result<StateAddUpdCliArgs, CliError> {
+pub(crate) fn state_add_upd_parse_args(args: &[String]) -> Result<StateAddUpdCliArgs, CliError> {
     let mut update: bool = false;
     let mut src_addr: IpNet = IpNet::default();
     let mut dst_addr: IpNet = IpNet::default();
@@ -1218,16 +1206,13 @@ pub(crate) fn state_add_upd_parse_args(args: &Vec<String>) -> Result<StateAddUpd
                         mark_and_mask = Some((mark_32, u32::MAX));
 
                         if let Some((_p, m)) = it.peek() {
-                            match m.as_str() {
-                                "mask" => {
-                                    it.next();
-                                    if let Some((_p, mask)) = it.next() {
-                                        if let Ok(mask_32) = mask.parse::<u32>() {
-                                            mark_and_mask = Some((mark_32, mask_32));
-                                        }
+                            if m.as_str() == "mask" {
+                                it.next();
+                                if let Some((_p, mask)) = it.next() {
+                                    if let Ok(mask_32) = mask.parse::<u32>() {
+                                        mark_and_mask = Some((mark_32, mask_32));
                                     }
                                 }
-                                _ => (),
                             }
                         }
                     }
@@ -1239,16 +1224,13 @@ pub(crate) fn state_add_upd_parse_args(args: &Vec<String>) -> Result<StateAddUpd
                         output_mark_and_mask = Some((mark_32, u32::MAX));
 
                         if let Some((_p, m)) = it.peek() {
-                            match m.as_str() {
-                                "mask" => {
-                                    it.next();
-                                    if let Some((_p, mask)) = it.next() {
-                                        if let Ok(mask_32) = mask.parse::<u32>() {
-                                            output_mark_and_mask = Some((mark_32, mask_32));
-                                        }
+                            if m.as_str() == "mask" {
+                                it.next();
+                                if let Some((_p, mask)) = it.next() {
+                                    if let Ok(mask_32) = mask.parse::<u32>() {
+                                        output_mark_and_mask = Some((mark_32, mask_32));
                                     }
                                 }
-                                _ => (),
                             }
                         }
                     }
@@ -1697,7 +1679,7 @@ pub struct StateDelGetCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn state_del_get_parse_args(args: &Vec<String>) -> Result<StateDelGetCliArgs, CliError> {
+pub(crate) fn state_del_get_parse_args(args: &[String]) -> Result<StateDelGetCliArgs, CliError> {
     let mut delete = true;
     let mut src_addr: IpNet = IpNet::default();
     let mut dst_addr: IpNet = IpNet::default();
@@ -1756,16 +1738,13 @@ pub(crate) fn state_del_get_parse_args(args: &Vec<String>) -> Result<StateDelGet
                         mark_and_mask = Some((mark_32, u32::MAX));
 
                         if let Some((_p, m)) = it.peek() {
-                            match m.as_str() {
-                                "mask" => {
-                                    it.next();
-                                    if let Some((_p, mask)) = it.next() {
-                                        if let Ok(mask_32) = mask.parse::<u32>() {
-                                            mark_and_mask = Some((mark_32, mask_32));
-                                        }
+                            if m.as_str() == "mask" {
+                                it.next();
+                                if let Some((_p, mask)) = it.next() {
+                                    if let Ok(mask_32) = mask.parse::<u32>() {
+                                        mark_and_mask = Some((mark_32, mask_32));
                                     }
                                 }
-                                _ => (),
                             }
                         }
                     }
@@ -1795,7 +1774,7 @@ pub struct StateDumpCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn state_dump_parse_args(args: &Vec<String>) -> Result<StateDumpCliArgs, CliError> {
+pub(crate) fn state_dump_parse_args(args: &[String]) -> Result<StateDumpCliArgs, CliError> {
     let mut src_addr: IpNet = IpNet::default();
     let mut dst_addr: IpNet = IpNet::default();
 
@@ -1834,7 +1813,7 @@ pub struct StateFlushCliArgs {
 }
 
 #[allow(dead_code)]
-pub(crate) fn state_flush_parse_args(args: &Vec<String>) -> Result<StateFlushCliArgs, CliError> {
+pub(crate) fn state_flush_parse_args(args: &[String]) -> Result<StateFlushCliArgs, CliError> {
     let mut protocol: Option<u8> = None;
 
     let mut it = args.iter().enumerate().peekable();
@@ -1881,7 +1860,7 @@ pub struct StateAllocSpiCliArgs {
 
 #[allow(dead_code)]
 pub(crate) fn state_alloc_spi_parse_args(
-    args: &Vec<String>,
+    args: &[String],
 ) -> Result<StateAllocSpiCliArgs, CliError> {
     let mut src_addr: IpNet = IpNet::default();
     let mut dst_addr: IpNet = IpNet::default();
@@ -1940,16 +1919,13 @@ pub(crate) fn state_alloc_spi_parse_args(
                         mark_and_mask = Some((mark_32, u32::MAX));
 
                         if let Some((_p, m)) = it.peek() {
-                            match m.as_str() {
-                                "mask" => {
-                                    it.next();
-                                    if let Some((_p, mask)) = it.next() {
-                                        if let Ok(mask_32) = mask.parse::<u32>() {
-                                            mark_and_mask = Some((mark_32, mask_32));
-                                        }
+                            if m.as_str() == "mask" {
+                                it.next();
+                                if let Some((_p, mask)) = it.next() {
+                                    if let Ok(mask_32) = mask.parse::<u32>() {
+                                        mark_and_mask = Some((mark_32, mask_32));
                                     }
                                 }
-                                _ => (),
                             }
                         }
                     }
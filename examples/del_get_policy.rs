@@ -19,7 +19,7 @@ async fn main() -> Result<(), Error> {
     let cli_args = match policy_del_get_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     };
@@ -42,11 +42,8 @@ async fn main() -> Result<(), Error> {
 }
 
 async fn get_policy(handle: Handle, ca: &PolicyDelGetCliArgs) -> Result<(), Error> {
-    let mut req = if ca.index.is_some() {
-        handle
-            .policy()
-            .get_index(ca.index.unwrap())
-            .direction(ca.direction)
+    let mut req = if let Some(index) = ca.index {
+        handle.policy().get_index(index).direction(ca.direction)
     } else {
         handle
             .policy()
@@ -109,11 +106,8 @@ async fn get_policy(handle: Handle, ca: &PolicyDelGetCliArgs) -> Result<(), Erro
 }
 
 async fn del_policy(handle: Handle, ca: &PolicyDelGetCliArgs) -> Result<(), Error> {
-    let mut req = if ca.index.is_some() {
-        handle
-            .policy()
-            .delete_index(ca.index.unwrap())
-            .direction(ca.direction)
+    let mut req = if let Some(index) = ca.index {
+        handle.policy().delete_index(index).direction(ca.direction)
     } else {
         handle
             .policy()
@@ -19,7 +19,7 @@ async fn main() -> Result<(), Error> {
     let cli_args = match state_del_get_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     };
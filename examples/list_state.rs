@@ -14,7 +14,7 @@ async fn main() -> Result<(), Error> {
     let cli_args = match state_dump_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             usage();
             std::process::exit(1);
         }
@@ -4,16 +4,18 @@
 
 use futures::stream::StreamExt;
 use netlink_packet_core::NetlinkPayload;
+use netlink_packet_xfrm::XfrmMessage;
 use netlink_proto::sys::{AsyncSocket, SocketAddr};
-use xfrmnetlink::new_connection;
+use xfrmnetlink::{new_connection, XfrmGroups, XfrmMapping, XfrmReport};
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
     // Open the netlink socket
     let (mut connection, _, mut messages) = new_connection().map_err(|e| format!("{}", e))?;
 
-    // Can specify various XFRMNLGRP_* flags to specify specific messages to listen for.
-    let xfrmgrp_flags: u32 = u32::MAX;
+    // Can specify a set of XFRMNLGRP_* groups to only listen for specific messages,
+    // e.g. XfrmGroups::EXPIRE | XfrmGroups::ACQUIRE.
+    let xfrmgrp_flags: u32 = XfrmGroups::all().bits();
 
     // A netlink socket address is created with said flags.
     let addr = SocketAddr::new(0, xfrmgrp_flags);
@@ -35,6 +37,8 @@ async fn main() -> Result<(), String> {
             // Could match on XfrmMessage enum variants to print something unique
             match xfrm_msg {
                 //XfrmMessage::AddSa(_m) => {}
+                XfrmMessage::Mapping(m) => println!("XFRM mapping - {:?}", XfrmMapping::from(m)),
+                XfrmMessage::Report(m) => println!("XFRM report - {:?}", XfrmReport::from(m)),
                 _ => println!("XFRM event message - {:?}", xfrm_msg),
             };
         } else {
@@ -13,7 +13,7 @@ async fn main() -> Result<(), ()> {
     let cli_args = match policy_spd_parse_args(&args) {
         Ok(parsed_args) => parsed_args,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             usage();
             std::process::exit(1);
         }
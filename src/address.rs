@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::Error;
+use netlink_packet_xfrm::{Address, Selector};
+
+pub(crate) const AF_INET: u16 = 2;
+pub(crate) const AF_INET6: u16 = 10;
+
+// Convert a kernel xfrm_address_t back into an IpAddr,
+// using the family carried alongside it in the message.
+pub(crate) fn address_to_ip(addr: &Address, family: u16) -> Option<IpAddr> {
+    match family {
+        AF_INET => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(&addr.addr[0..4]);
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        AF_INET6 => Some(IpAddr::V6(Ipv6Addr::from(addr.addr))),
+        _ => None,
+    }
+}
+
+// Same as address_to_ip, but falls back to the raw 16 bytes as an IPv6
+// address for messages with an unknown family (e.g. AF_UNSPEC).
+pub(crate) fn address_to_ip_lossy(addr: &Address, family: u16) -> IpAddr {
+    address_to_ip(addr, family).unwrap_or(IpAddr::V6(Ipv6Addr::from(addr.addr)))
+}
+
+// Equivalent to the kernel's addr_match(), returns true if the first
+// prefix_len bits of addr and prefix are the same.
+pub(crate) fn prefix_match(addr: &IpAddr, prefix: &IpAddr, prefix_len: u8) -> bool {
+    match (addr, prefix) {
+        (IpAddr::V4(a), IpAddr::V4(p)) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len.min(32)))
+                .unwrap_or(0);
+            (u32::from(*a) & mask) == (u32::from(*p) & mask)
+        }
+        (IpAddr::V6(a), IpAddr::V6(p)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len.min(128)))
+                .unwrap_or(0);
+            (u128::from(*a) & mask) == (u128::from(*p) & mask)
+        }
+        _ => false,
+    }
+}
+
+// Returns true if the selector source and destination addresses fall within the
+// given prefixes. This mirrors how the kernel applies XFRMA_ADDRESS_FILTER to states.
+pub(crate) fn selector_match(
+    sel: &Selector,
+    src_prefix: &IpAddr,
+    src_prefix_len: u8,
+    dst_prefix: &IpAddr,
+    dst_prefix_len: u8,
+) -> bool {
+    let src = address_to_ip(&sel.saddr, sel.family);
+    let dst = address_to_ip(&sel.daddr, sel.family);
+
+    match (src, dst) {
+        (Some(src), Some(dst)) => {
+            prefix_match(&src, src_prefix, src_prefix_len)
+                && prefix_match(&dst, dst_prefix, dst_prefix_len)
+        }
+        _ => false,
+    }
+}
+
+// IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) are accepted by the kernel, but IPv4
+// traffic is never matched against IPv6 states and selectors, so they silently
+// never match. Reject them instead, the IPv4 address should be used.
+fn is_ipv4_mapped(addr: &Ipv6Addr) -> bool {
+    matches!(addr.segments(), [0, 0, 0, 0, 0, 0xffff, _, _])
+}
+
+pub(crate) fn check_not_mapped(addr: &IpAddr) -> Result<(), Error> {
+    match addr {
+        IpAddr::V6(v6) if is_ipv4_mapped(v6) => Err(Error::MappedAddress(*addr)),
+        _ => Ok(()),
+    }
+}
+
+// Same as check_not_mapped, for an address already in a message.
+pub(crate) fn check_address_not_mapped(addr: &Address, family: u16) -> Result<(), Error> {
+    match family {
+        AF_INET6 => check_not_mapped(&IpAddr::V6(Ipv6Addr::from(addr.addr))),
+        _ => Ok(()),
+    }
+}
+
+// Reject selector prefix lengths wider than the address family, which the
+// kernel would otherwise refuse with a bare EINVAL, and IPv4-mapped addresses.
+pub(crate) fn check_selector(sel: &Selector) -> Result<(), Error> {
+    let max_prefix_len = match sel.family {
+        AF_INET => 32,
+        AF_INET6 => 128,
+        _ => return Ok(()),
+    };
+
+    if sel.prefixlen_s > max_prefix_len {
+        return Err(Error::InvalidSelector {
+            reason: format!(
+                "source prefix length {} exceeds {} bits",
+                sel.prefixlen_s, max_prefix_len
+            ),
+        });
+    }
+    if sel.prefixlen_d > max_prefix_len {
+        return Err(Error::InvalidSelector {
+            reason: format!(
+                "destination prefix length {} exceeds {} bits",
+                sel.prefixlen_d, max_prefix_len
+            ),
+        });
+    }
+
+    // Only the bits covered by the prefix matter, e.g. ::/0 is fine.
+    if sel.prefixlen_s > 0 {
+        check_address_not_mapped(&sel.saddr, sel.family)?;
+    }
+    if sel.prefixlen_d > 0 {
+        check_address_not_mapped(&sel.daddr, sel.family)?;
+    }
+    Ok(())
+}
+
+// Validate selector addresses up front, for the fallible try_* constructors.
+pub(crate) fn check_prefixes(
+    src_addr: &IpAddr,
+    src_prefix_len: u8,
+    dst_addr: &IpAddr,
+    dst_prefix_len: u8,
+) -> Result<(), Error> {
+    check_families(src_addr, dst_addr)?;
+
+    let max_prefix_len = if src_addr.is_ipv4() { 32 } else { 128 };
+    if src_prefix_len > max_prefix_len || dst_prefix_len > max_prefix_len {
+        return Err(Error::InvalidSelector {
+            reason: format!(
+                "prefix lengths {}/{} exceed {} bits",
+                src_prefix_len, dst_prefix_len, max_prefix_len
+            ),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn check_families(src_addr: &IpAddr, dst_addr: &IpAddr) -> Result<(), Error> {
+    check_not_mapped(src_addr)?;
+    check_not_mapped(dst_addr)?;
+
+    if src_addr.is_ipv4() != dst_addr.is_ipv4() {
+        return Err(Error::InvalidSelector {
+            reason: format!(
+                "source {} and destination {} are of different families",
+                src_addr, dst_addr
+            ),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures::{
+    channel::oneshot,
+    future::{self, Either, FutureExt, Shared},
+    stream::{Stream, StreamExt},
+};
+
+/// Cancels response streams, e.g. an outstanding dump when a daemon times out.
+///
+/// Streams wrapped with `wrap` end (yield `None`) as soon as `cancel` is called on the
+/// token or any of its clones, even while waiting for the next response. Cancelling is
+/// the same as dropping the stream, with the same guarantees (see `Handle::request`):
+/// the request has already been sent, and the kernel finishes processing it. The
+/// connection keeps reading the remaining responses and discards them, so nothing is
+/// leaked and the next requests on the connection are not affected.
+///
+/// ```no_run
+/// # async fn run(handle: xfrmnetlink::Handle) -> Result<(), xfrmnetlink::Error> {
+/// use futures::stream::TryStreamExt;
+/// use xfrmnetlink::CancelToken;
+///
+/// let token = CancelToken::new();
+/// let mut states = token.wrap(handle.state().get_dump().execute().into_stream());
+///
+/// // e.g. from a timeout or a shutdown signal
+/// let canceller = token.clone();
+/// tokio::spawn(async move { canceller.cancel() });
+///
+/// while let Some(state) = states.try_next().await? {
+///     println!("{:?}", state);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CancelToken {
+    sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    cancelled: Shared<oneshot::Receiver<()>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+        CancelToken {
+            sender: Arc::new(Mutex::new(Some(sender))),
+            cancelled: receiver.shared(),
+        }
+    }
+
+    /// Cancel every stream wrapped with this token, and the ones wrapped later.
+    pub fn cancel(&self) {
+        if let Some(sender) = self.sender.lock().unwrap().take() {
+            let _ = sender.send(());
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.sender.lock().unwrap().is_none()
+    }
+
+    /// End `stream` early when the token is cancelled.
+    pub fn wrap<S>(&self, stream: S) -> impl Stream<Item = S::Item>
+    where
+        S: Stream,
+    {
+        // The receiver fails without a cancellation when the sender is dropped,
+        // i.e. when every token is dropped. The stream then runs to completion.
+        let cancelled = self.cancelled.clone().then(|result| match result {
+            Ok(()) => Either::Left(future::ready(())),
+            Err(_) => Either::Right(future::pending()),
+        });
+        stream.take_until(cancelled)
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
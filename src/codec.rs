@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: MIT
+
+use crate::Error;
+use netlink_packet_core::NetlinkMessage;
+use netlink_packet_utils::Emitable;
+use netlink_packet_xfrm::{XfrmAttrs, XfrmMessage};
+
+/// Serialize a netlink message (e.g. one returned by a request's `into_message()`)
+/// into the bytes that would be written to the socket.
+/// The header length and message type are filled in before serializing.
+pub fn encode_message(message: &mut NetlinkMessage<XfrmMessage>) -> Vec<u8> {
+    message.finalize();
+
+    let mut buf = vec![0; message.buffer_len()];
+    message.serialize(&mut buf[..]);
+    buf
+}
+
+// Some messages (e.g. XFRM_MSG_GETAE and XFRM_MSG_EXPIRE) have no attribute list in
+// netlink-packet-xfrm, but the kernel reads attributes such as XFRMA_MARK after their
+// fixed header. Serialize those with the attributes appended, as a raw message of
+// the same type.
+pub(crate) fn with_attributes(message: XfrmMessage, nlas: &[XfrmAttrs]) -> XfrmMessage {
+    if nlas.is_empty() {
+        return message;
+    }
+
+    let header_len = message.buffer_len();
+    let mut buf = vec![0; header_len + nlas.buffer_len()];
+    message.emit(&mut buf[..header_len]);
+    nlas.emit(&mut buf[header_len..]);
+    XfrmMessage::Other((message.message_type(), buf))
+}
+
+/// Parse bytes, such as a capture of an `ip xfrm` request, back into a netlink message.
+pub fn decode_message(buf: &[u8]) -> Result<NetlinkMessage<XfrmMessage>, Error> {
+    NetlinkMessage::<XfrmMessage>::deserialize(buf).map_err(|e| Error::Decode(e.to_string()))
+}
+
+// Golden messages, checked field by field against the kernel's uapi structs. The
+// integers are in host byte order, so these only hold on little endian hosts.
+#[cfg(all(test, target_endian = "little"))]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::{mock_handle, UserTemplateBuilder};
+    use netlink_packet_xfrm::{IPPROTO_ESP, XFRM_MODE_TUNNEL, XFRM_POLICY_OUT};
+
+    const SRC: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const DST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    // ip xfrm policy add src 10.0.0.0/24 dst 10.0.1.0/24 dir out priority 100 \
+    //     tmpl src 192.0.2.1 dst 192.0.2.2 proto esp mode tunnel reqid 1
+    const POLICY_ADD: &[&str] = &[
+        // nlmsghdr
+        "fc000000130005000000000000000000",
+        // xfrm_userpolicy_info: selector
+        "0a0001000000000000000000000000000a000000000000000000000000000000",
+        "000000000000000002001818000000000000000000000000",
+        // lifetime_cfg
+        "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        // lifetime_cur
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        // priority, index, dir, action, flags, share
+        "64000000000000000100000000000000",
+        // XFRMA_TMPL
+        "44000500",
+        // xfrm_user_tmpl
+        "c0000202000000000000000000000000000000003200000002000000c0000201",
+        "0000000000000000000000000100000001000000ffffffffffffffffffffffff",
+    ];
+
+    // ip xfrm state add src 192.0.2.1 dst 192.0.2.2 proto esp spi 0x1000 mode tunnel \
+    //     reqid 1 enc "cbc(aes)" 0x0102..10 auth-trunc "hmac(sha256)" 0x0102..20 128
+    const STATE_ADD: &[&str] = &[
+        // nlmsghdr
+        "b4010000100005000000000000000000",
+        // xfrm_usersa_info: selector
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "000000000000000000000000000000000000000000000000",
+        // id
+        "c00002020000000000000000000000000000100032000000",
+        // saddr
+        "c0000201000000000000000000000000",
+        // lifetime_cfg
+        "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        // lifetime_cur
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        // stats, seq, reqid, family, mode, replay_window, flags
+        "0000000000000000000000000000000001000000020001000000000000000000",
+        // XFRMA_ALG_CRYPT
+        "58000200",
+        // xfrm_algo: "cbc(aes)", 128 bits
+        "6362632861657329000000000000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "800000000102030405060708090a0b0c0d0e0f10",
+        // XFRMA_ALG_AUTH_TRUNC
+        "6c001400",
+        // xfrm_algo_auth: "hmac(sha256)", 256 bits, truncated to 128
+        "686d616328736861323536290000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "00010000800000000102030405060708090a0b0c0d0e0f101112131415161718",
+        "191a1b1c1d1e1f20",
+    ];
+
+    fn golden(lines: &[&str]) -> Vec<u8> {
+        hex::decode(lines.concat()).unwrap()
+    }
+
+    fn check(mut message: NetlinkMessage<XfrmMessage>, expected: &[u8]) {
+        assert_eq!(encode_message(&mut message), expected);
+
+        let decoded = decode_message(expected).unwrap();
+        assert_eq!(decoded.header, message.header);
+        assert_eq!(decoded.payload, message.payload);
+    }
+
+    #[tokio::test]
+    async fn policy_add() {
+        let (handle, _) = mock_handle();
+        let message = handle
+            .policy()
+            .add(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)),
+                24,
+                IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)),
+                24,
+            )
+            .direction(XFRM_POLICY_OUT)
+            .priority(100)
+            .add_template(
+                UserTemplateBuilder::new(SRC, DST)
+                    .protocol(IPPROTO_ESP)
+                    .mode(XFRM_MODE_TUNNEL)
+                    .reqid(1),
+            )
+            .into_message();
+
+        check(message, &golden(POLICY_ADD));
+    }
+
+    #[tokio::test]
+    async fn state_add_with_crypto() {
+        let (handle, _) = mock_handle();
+        let enc_key: Vec<u8> = (1..=16).collect();
+        let auth_key: Vec<u8> = (1..=32).collect();
+        let message = handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x1000)
+            .mode(XFRM_MODE_TUNNEL)
+            .reqid(1)
+            .encryption("cbc(aes)", &enc_key)
+            .unwrap()
+            .authentication_trunc("hmac(sha256)", &auth_key, 128)
+            .unwrap()
+            .into_message();
+
+        check(message, &golden(STATE_ADD));
+    }
+}
@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: MIT
 
+use std::convert::TryFrom;
 use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
 
 use futures::channel::mpsc::UnboundedReceiver;
 
@@ -10,6 +13,36 @@ use netlink_packet_xfrm::XfrmMessage;
 use netlink_proto::sys::{protocols::NETLINK_XFRM, AsyncSocket, SocketAddr};
 use netlink_proto::Connection;
 
+#[cfg(feature = "smol_socket")]
+use netlink_proto::sys::SmolSocket;
+
+/// Create a new xfrm netlink connection, using the tokio based socket.
+///
+/// Returns the `Connection` future, which owns the socket and must be spawned (or
+/// otherwise polled) for requests to make progress, a `Handle` to send requests, and
+/// the receiver for messages that are not replies to a request (e.g. from multicast groups).
+///
+/// The `Connection` future completes, closing the socket, once every `Handle` (including
+/// the clones held by request builders) and the message receiver have been dropped.
+/// To stop it while handles may still be alive, e.g. when reloading a service, wrap it
+/// with `futures::future::abortable` and abort it; the socket is closed when the aborted
+/// future is dropped:
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// let (connection, handle, _) = xfrmnetlink::new_connection()?;
+/// let (connection, shutdown) = futures::future::abortable(connection);
+/// tokio::spawn(connection);
+///
+/// // ... use the handle ...
+///
+/// // New requests fail with Error::RequestFailed after this,
+/// // and the responses still pending end early.
+/// shutdown.abort();
+/// # drop(handle);
+/// # Ok(())
+/// # }
+/// ```
 #[cfg(feature = "tokio_socket")]
 #[allow(clippy::type_complexity)]
 pub fn new_connection() -> io::Result<(
@@ -20,6 +53,18 @@ pub fn new_connection() -> io::Result<(
     new_connection_with_socket()
 }
 
+/// Same as `new_connection`, but using the smol based socket.
+/// Requires the `smol_socket` feature.
+#[cfg(feature = "smol_socket")]
+#[allow(clippy::type_complexity)]
+pub fn new_smol_connection() -> io::Result<(
+    Connection<XfrmMessage, SmolSocket>,
+    Handle,
+    UnboundedReceiver<(NetlinkMessage<XfrmMessage>, SocketAddr)>,
+)> {
+    new_connection_with_socket()
+}
+
 #[allow(clippy::type_complexity)]
 pub fn new_connection_with_socket<S>() -> io::Result<(
     Connection<XfrmMessage, S>,
@@ -32,3 +77,98 @@ where
     let (conn, handle, messages) = netlink_proto::new_connection_with_socket(NETLINK_XFRM)?;
     Ok((conn, Handle::new(handle), messages))
 }
+
+/// Same as `new_connection`, but the netlink socket is bound to the given port id
+/// instead of letting the kernel assign one when the first message is sent.
+#[cfg(feature = "tokio_socket")]
+#[allow(clippy::type_complexity)]
+pub fn new_connection_with_port(
+    port: u32,
+) -> io::Result<(
+    Connection<XfrmMessage>,
+    Handle,
+    UnboundedReceiver<(NetlinkMessage<XfrmMessage>, SocketAddr)>,
+)> {
+    new_connection_with_socket_and_port(port)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn new_connection_with_socket_and_port<S>(
+    port: u32,
+) -> io::Result<(
+    Connection<XfrmMessage, S>,
+    Handle,
+    UnboundedReceiver<(NetlinkMessage<XfrmMessage>, SocketAddr)>,
+)>
+where
+    S: AsyncSocket,
+{
+    let (mut conn, handle, messages) = new_connection_with_socket::<S>()?;
+    conn.socket_mut()
+        .socket_mut()
+        .bind(&SocketAddr::new(port, 0))?;
+    Ok((conn, handle, messages))
+}
+
+/// Set the receive buffer size of the connection's netlink socket, and return the size
+/// the kernel reports back.
+///
+/// Large dumps can overrun a small receive buffer, causing the kernel to drop messages and
+/// the dump to come back incomplete. This must be called on the `Connection` before it is
+/// spawned, since the `Handle` has no access to the underlying socket.
+///
+/// SO_RCVBUF is capped by the `net.core.rmem_max` sysctl. If the kernel grants less than
+/// `size`, SO_RCVBUFFORCE is tried, which ignores the cap but requires CAP_NET_ADMIN;
+/// without it the capped size is kept. The kernel doubles the requested value to
+/// account for its bookkeeping, so the returned size is usually twice the one set.
+pub fn set_rx_buf_size<S>(
+    connection: &mut Connection<XfrmMessage, S>,
+    size: usize,
+) -> io::Result<usize>
+where
+    S: AsyncSocket,
+{
+    let socket = connection.socket_mut().socket_mut();
+    let size = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+
+    setsockopt_int(socket.as_raw_fd(), libc::SO_RCVBUF, size)?;
+    if socket.get_rx_buf_sz()? < size as usize {
+        match setsockopt_int(socket.as_raw_fd(), libc::SO_RCVBUFFORCE, size) {
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => {}
+            result => result?,
+        }
+    }
+    socket.get_rx_buf_sz()
+}
+
+fn setsockopt_int(fd: RawFd, option: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    // SAFETY: the value is a c_int, and its size is passed along with it.
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockSocket;
+
+    #[test]
+    fn set_rx_buf_size_reports_the_size() {
+        let (mut connection, _, _) = new_connection_with_socket::<MockSocket>().unwrap();
+
+        let size = set_rx_buf_size(&mut connection, 32768).unwrap();
+
+        assert!(size >= 32768, "{}", size);
+    }
+}
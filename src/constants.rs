@@ -1,5 +1,8 @@
 // SPDX-License-Identifier: MIT
 
+use bitflags::bitflags;
+use std::convert::TryFrom;
+
 // Some handy strings for specifying xfrm encryption/authentication/compression algorithms.
 // Current as of Linux 5.18.10
 
@@ -38,3 +41,398 @@ pub const ENC_AEAD_ALG_AES_GCM: &str = "rfc4106(gcm(aes))";
 pub const ENC_AEAD_ALG_AES_CCM: &str = "rfc4309(ccm(aes))";
 pub const ENC_AEAD_ALG_NULL_AES_GMAC: &str = "rfc4543(gcm(aes))";
 pub const ENC_AEAD_ALG_CHACHA20_POLY1305: &str = "rfc7539esp(chacha20,poly1305)";
+
+/// Returns the valid key lengths, in bits, for the known algorithms above, or `None`
+/// if the algorithm isn't known or takes keys of any length. For AES-CTR and the AEAD
+/// algorithms the key includes the trailing salt/nonce (32 bits, or 24 bits for CCM),
+/// just as the kernel expects it.
+///
+/// HMAC keys can be of any length, so the HMAC algorithms return `None`. A key as long
+/// as the hash output (e.g. 256 bits for hmac(sha256)) is the usual choice, see RFC 2104.
+pub fn alg_key_lengths(alg_name: &str) -> Option<Vec<u32>> {
+    let lengths = match alg_name {
+        AUTH_ALG_NULL | ENC_ALG_NULL => vec![0],
+        AUTH_ALG_AES_XCBC => vec![128],
+        AUTH_ALG_AES_CMAC => vec![128, 192, 256],
+        ENC_ALG_DES_CBC => vec![64],
+        ENC_ALG_3DES_CBC => vec![192],
+        ENC_ALG_CAST_CBC => (40..=128).step_by(8).collect(),
+        ENC_ALG_BLOWFISH_CBC => (40..=448).step_by(8).collect(),
+        ENC_ALG_AES_CBC | ENC_ALG_SERPENT_CBC | ENC_ALG_CAMELLIA_CBC | ENC_ALG_TWOFISH_CBC => {
+            vec![128, 192, 256]
+        }
+        ENC_ALG_SM4_CBC => vec![128],
+        ENC_ALG_AES_CTR | ENC_AEAD_ALG_AES_GCM | ENC_AEAD_ALG_NULL_AES_GMAC => {
+            vec![160, 224, 288]
+        }
+        ENC_AEAD_ALG_AES_CCM => vec![152, 216, 280],
+        ENC_AEAD_ALG_CHACHA20_POLY1305 => vec![288],
+        _ => return None,
+    };
+    Some(lengths)
+}
+
+/// Returns the standard truncation length (ICV length), in bits, of the known
+/// authentication algorithms above, or `None` if the algorithm isn't known or has none.
+pub fn auth_trunc_len(alg_name: &str) -> Option<u32> {
+    match alg_name {
+        // RFC 2403, RFC 2404, RFC 2857, RFC 3566, RFC 4494
+        AUTH_ALG_MD5_HMAC
+        | AUTH_ALG_SHA1_HMAC
+        | AUTH_ALG_RIPEMD_160_HMAC
+        | AUTH_ALG_AES_XCBC
+        | AUTH_ALG_AES_CMAC => Some(96),
+        // RFC 4868
+        AUTH_ALG_SHA2_256_HMAC => Some(128),
+        AUTH_ALG_SHA2_384_HMAC => Some(192),
+        AUTH_ALG_SHA2_512_HMAC => Some(256),
+        _ => None,
+    }
+}
+
+/// Returns the valid ICV lengths, in bits, for the known AEAD algorithms above,
+/// or `None` if the algorithm isn't a known AEAD.
+pub fn aead_icv_lengths(alg_name: &str) -> Option<Vec<u32>> {
+    let lengths = match alg_name {
+        ENC_AEAD_ALG_AES_GCM | ENC_AEAD_ALG_AES_CCM => vec![64, 96, 128],
+        ENC_AEAD_ALG_NULL_AES_GMAC | ENC_AEAD_ALG_CHACHA20_POLY1305 => vec![128],
+        _ => return None,
+    };
+    Some(lengths)
+}
+
+/// SA sharing mode for a policy template (the `share` field of `xfrm_user_tmpl`).
+/// Governs whether SAs resolved from the template may be reused across flows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum ShareMode {
+    /// No limitations, any SA may be reused (XFRM_SHARE_ANY)
+    Any,
+    /// One SA per session (XFRM_SHARE_SESSION)
+    Session,
+    /// One SA per user (XFRM_SHARE_USER)
+    User,
+    /// A unique SA for every flow (XFRM_SHARE_UNIQUE)
+    Unique,
+}
+
+impl From<ShareMode> for u8 {
+    fn from(mode: ShareMode) -> u8 {
+        match mode {
+            ShareMode::Any => 0,
+            ShareMode::Session => 1,
+            ShareMode::User => 2,
+            ShareMode::Unique => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for ShareMode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ShareMode::Any),
+            1 => Ok(ShareMode::Session),
+            2 => Ok(ShareMode::User),
+            3 => Ok(ShareMode::Unique),
+            _ => Err(value),
+        }
+    }
+}
+
+/// The direction of a policy (the `dir` field of `xfrm_userpolicy_info`).
+/// Socket policies (XFRM_POLICY_MASK and above) are not set through these requests.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum PolicyDirection {
+    /// Inbound traffic to the host (XFRM_POLICY_IN)
+    In,
+    /// Outbound traffic from the host (XFRM_POLICY_OUT)
+    Out,
+    /// Traffic forwarded by the host, e.g. on a gateway (XFRM_POLICY_FWD)
+    Fwd,
+}
+
+impl From<PolicyDirection> for u8 {
+    fn from(direction: PolicyDirection) -> u8 {
+        match direction {
+            PolicyDirection::In => 0,
+            PolicyDirection::Out => 1,
+            PolicyDirection::Fwd => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for PolicyDirection {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PolicyDirection::In),
+            1 => Ok(PolicyDirection::Out),
+            2 => Ok(PolicyDirection::Fwd),
+            _ => Err(value),
+        }
+    }
+}
+
+// netlink-packet-xfrm doesn't know about XFRMA_SA_DIR (Linux 6.10) yet, so it is
+// sent as a raw attribute.
+pub(crate) const XFRMA_SA_DIR: u16 = 33;
+
+/// The direction of a state (XFRMA_SA_DIR), see `StateModifyRequest::sa_direction`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum SaDirection {
+    /// An inbound state (XFRM_SA_DIR_IN)
+    In,
+    /// An outbound state (XFRM_SA_DIR_OUT)
+    Out,
+}
+
+impl From<SaDirection> for u8 {
+    fn from(direction: SaDirection) -> u8 {
+        match direction {
+            SaDirection::In => 1,
+            SaDirection::Out => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for SaDirection {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(SaDirection::In),
+            2 => Ok(SaDirection::Out),
+            _ => Err(value),
+        }
+    }
+}
+
+/// The mode of a state or policy template (the `mode` field of `xfrm_usersa_info`
+/// and `xfrm_user_tmpl`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum XfrmMode {
+    /// XFRM_MODE_TRANSPORT
+    Transport,
+    /// XFRM_MODE_TUNNEL
+    Tunnel,
+    /// XFRM_MODE_ROUTEOPTIMIZATION (Mobile IPv6)
+    RouteOptimization,
+    /// XFRM_MODE_IN_TRIGGER (Mobile IPv6)
+    InTrigger,
+    /// XFRM_MODE_BEET
+    Beet,
+    /// XFRM_MODE_IPTFS, IP-TFS/AGGFRAG (Linux 6.14)
+    IpTfs,
+    /// A mode this crate doesn't know about
+    Unknown(u8),
+}
+
+impl From<XfrmMode> for u8 {
+    fn from(mode: XfrmMode) -> u8 {
+        match mode {
+            XfrmMode::Transport => 0,
+            XfrmMode::Tunnel => 1,
+            XfrmMode::RouteOptimization => 2,
+            XfrmMode::InTrigger => 3,
+            XfrmMode::Beet => 4,
+            XfrmMode::IpTfs => 5,
+            XfrmMode::Unknown(mode) => mode,
+        }
+    }
+}
+
+impl From<u8> for XfrmMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => XfrmMode::Transport,
+            1 => XfrmMode::Tunnel,
+            2 => XfrmMode::RouteOptimization,
+            3 => XfrmMode::InTrigger,
+            4 => XfrmMode::Beet,
+            5 => XfrmMode::IpTfs,
+            _ => XfrmMode::Unknown(value),
+        }
+    }
+}
+
+/// Address families for `StateModifyRequest::family` and `selector_family`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum AddressFamily {
+    /// AF_UNSPEC, e.g. for states with wildcard addresses
+    Unspec,
+    /// AF_INET
+    Inet,
+    /// AF_INET6
+    Inet6,
+}
+
+impl From<AddressFamily> for u16 {
+    fn from(family: AddressFamily) -> u16 {
+        match family {
+            AddressFamily::Unspec => 0,
+            AddressFamily::Inet => 2,
+            AddressFamily::Inet6 => 10,
+        }
+    }
+}
+
+impl TryFrom<u16> for AddressFamily {
+    type Error = u16;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AddressFamily::Unspec),
+            2 => Ok(AddressFamily::Inet),
+            10 => Ok(AddressFamily::Inet6),
+            _ => Err(value),
+        }
+    }
+}
+
+/// NAT traversal encapsulation types for `StateModifyRequest::encapsulation`.
+///
+/// ESP-in-TCP (RFC 8229) requires Linux 5.6 or newer for IPv4, and Linux 5.8 for IPv6.
+/// Older kernels, or kernels built without CONFIG_INET_ESPINTCP, reject it with EINVAL.
+/// `StateModifyRequest::execute` checks for it beforehand, and fails with
+/// `Error::EncapUnsupported` on kernels older than 5.6.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum EncapType {
+    /// draft-ietf-ipsec-nat-t-ike-00/01 (UDP_ENCAP_ESPINUDP_NON_IKE)
+    EspInUdpNonIke,
+    /// RFC 3948 (UDP_ENCAP_ESPINUDP)
+    EspInUdp,
+    /// RFC 8229 (TCP_ENCAP_ESPINTCP)
+    EspInTcp,
+    /// An encapsulation type this crate doesn't know about
+    Unknown(u16),
+}
+
+impl From<EncapType> for u16 {
+    fn from(encap_type: EncapType) -> u16 {
+        match encap_type {
+            EncapType::EspInUdpNonIke => 1,
+            EncapType::EspInUdp => 2,
+            EncapType::EspInTcp => 7,
+            EncapType::Unknown(encap_type) => encap_type,
+        }
+    }
+}
+
+impl From<u16> for EncapType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => EncapType::EspInUdpNonIke,
+            2 => EncapType::EspInUdp,
+            7 => EncapType::EspInTcp,
+            _ => EncapType::Unknown(value),
+        }
+    }
+}
+
+// Netlink multicast groups (enum xfrm_nlgroups), for NETLINK_ADD_MEMBERSHIP.
+pub const XFRMNLGRP_NONE: u32 = 0;
+pub const XFRMNLGRP_ACQUIRE: u32 = 1;
+pub const XFRMNLGRP_EXPIRE: u32 = 2;
+pub const XFRMNLGRP_SA: u32 = 3;
+pub const XFRMNLGRP_POLICY: u32 = 4;
+pub const XFRMNLGRP_AEVENTS: u32 = 5;
+pub const XFRMNLGRP_REPORT: u32 = 6;
+pub const XFRMNLGRP_MIGRATE: u32 = 7;
+pub const XFRMNLGRP_MAPPING: u32 = 8;
+
+bitflags! {
+    /// A set of xfrm multicast groups.
+    ///
+    /// `bits()` is the legacy group mask used when binding the socket (group N is bit
+    /// N - 1, e.g. `SocketAddr::new(0, groups.bits())`), and `groups()` lists the group
+    /// numbers for `add_membership`.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+    pub struct XfrmGroups: u32 {
+        const ACQUIRE = 1 << (XFRMNLGRP_ACQUIRE - 1);
+        const EXPIRE = 1 << (XFRMNLGRP_EXPIRE - 1);
+        const SA = 1 << (XFRMNLGRP_SA - 1);
+        const POLICY = 1 << (XFRMNLGRP_POLICY - 1);
+        const AEVENTS = 1 << (XFRMNLGRP_AEVENTS - 1);
+        const REPORT = 1 << (XFRMNLGRP_REPORT - 1);
+        const MIGRATE = 1 << (XFRMNLGRP_MIGRATE - 1);
+        const MAPPING = 1 << (XFRMNLGRP_MAPPING - 1);
+    }
+}
+
+impl XfrmGroups {
+    /// The XFRMNLGRP_* numbers of the groups in the set.
+    pub fn groups(&self) -> impl Iterator<Item = u32> {
+        let bits = self.bits();
+        (XFRMNLGRP_ACQUIRE..=XFRMNLGRP_MAPPING).filter(move |group| bits & (1 << (group - 1)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hmac_keys_of_any_length() {
+        for alg in [
+            AUTH_ALG_MD5_HMAC,
+            AUTH_ALG_SHA1_HMAC,
+            AUTH_ALG_SHA2_256_HMAC,
+            AUTH_ALG_SHA2_512_HMAC,
+            AUTH_ALG_SM3_HMAC,
+        ] {
+            assert_eq!(alg_key_lengths(alg), None, "{}", alg);
+        }
+        assert_eq!(alg_key_lengths(AUTH_ALG_AES_XCBC), Some(vec![128]));
+    }
+
+    #[test]
+    fn aes_gcm_lengths() {
+        let key_lengths = alg_key_lengths(ENC_AEAD_ALG_AES_GCM).unwrap();
+        // AES-128, AES-192 and AES-256, each followed by a 32-bit salt.
+        for aes_bits in [128, 192, 256] {
+            assert!(key_lengths.contains(&(aes_bits + 32)), "{}", aes_bits);
+            assert!(!key_lengths.contains(&aes_bits), "{}", aes_bits);
+        }
+        assert!(aead_icv_lengths(ENC_AEAD_ALG_AES_GCM)
+            .unwrap()
+            .contains(&128));
+    }
+
+    #[test]
+    fn share_mode_conversions() {
+        for (mode, value) in [
+            (ShareMode::Any, 0),
+            (ShareMode::Session, 1),
+            (ShareMode::User, 2),
+            (ShareMode::Unique, 3),
+        ] {
+            assert_eq!(u8::from(mode), value);
+            assert_eq!(ShareMode::try_from(value), Ok(mode));
+        }
+
+        assert_eq!(ShareMode::try_from(4), Err(4));
+    }
+
+    #[test]
+    fn encap_type_conversions() {
+        for (encap_type, value) in [
+            (EncapType::EspInUdpNonIke, 1),
+            (EncapType::EspInUdp, 2),
+            (EncapType::EspInTcp, 7),
+        ] {
+            assert_eq!(u16::from(encap_type), value);
+            assert_eq!(EncapType::from(value), encap_type);
+        }
+
+        assert_eq!(EncapType::from(3), EncapType::Unknown(3));
+        assert_eq!(u16::from(EncapType::Unknown(3)), 3);
+    }
+}
@@ -0,0 +1,372 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+
+use crate::address::address_to_ip_lossy;
+use netlink_packet_xfrm::{policy, state, LifetimeConfig, Selector, UserTemplate, XfrmAttrs};
+
+/// Render a state returned by a get or dump request in a layout similar to
+/// `ip xfrm state list`, e.g.
+///
+/// ```text
+/// src 10.0.0.1 dst 10.0.0.2
+///     proto esp spi 0x00001000 reqid 1 mode tunnel
+///     replay-window 32
+///     auth-trunc hmac(sha256) 0x... 128
+///     enc cbc(aes) 0x...
+///     sel src 0.0.0.0/0 dst 0.0.0.0/0
+///     lifetime config:
+///       ...
+/// ```
+///
+/// Key material is included, as it is with `ip xfrm state list`.
+pub fn format_state(state: &state::ModifyMessage) -> String {
+    StateDisplay(state).to_string()
+}
+
+/// Render a policy returned by a get or dump request in a layout similar to
+/// `ip xfrm policy list`, e.g.
+///
+/// ```text
+/// src 10.1.0.0/24 dst 10.2.0.0/24
+///     dir out priority 0
+///     tmpl src 10.0.0.1 dst 10.0.0.2
+///         proto esp spi 0x00000000 reqid 1 mode tunnel
+/// ```
+pub fn format_policy(policy: &policy::ModifyMessage) -> String {
+    PolicyDisplay(policy).to_string()
+}
+
+/// Describe a selector on one line, e.g. `udp 10.0.0.0/24:any -> 10.0.1.0/24:4500`.
+///
+/// The ports are shown for port based protocols (TCP, UDP, SCTP, DCCP, UDP-Lite), with
+/// their mask if it isn't a single port (e.g. `:1024/0xfc00`). For protocols that reuse
+/// the port fields, they are decoded as such: the type and code for ICMP, ICMPv6 and
+/// MH (`icmp 0.0.0.0/0 -> 10.0.0.1/32 type 8 code any`) and the key for GRE.
+pub fn describe_selector(sel: &Selector) -> String {
+    SelectorDescription(sel).to_string()
+}
+
+struct SelectorDescription<'a>(&'a Selector);
+
+impl fmt::Display for SelectorDescription<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sel = self.0;
+        let src = address_to_ip_lossy(&sel.saddr, sel.family);
+        let dst = address_to_ip_lossy(&sel.daddr, sel.family);
+
+        if sel.proto == 0 {
+            write!(f, "any ")?;
+        } else {
+            write!(f, "{} ", proto_name(sel.proto))?;
+        }
+
+        match sel.proto {
+            // TCP, UDP, DCCP, SCTP, UDP-Lite
+            6 | 17 | 33 | 132 | 136 => write!(
+                f,
+                "{}/{}:{} -> {}/{}:{}",
+                src,
+                sel.prefixlen_s,
+                port(sel.sport, sel.sport_mask),
+                dst,
+                sel.prefixlen_d,
+                port(sel.dport, sel.dport_mask)
+            )?,
+            _ => write!(
+                f,
+                "{}/{} -> {}/{}",
+                src, sel.prefixlen_s, dst, sel.prefixlen_d
+            )?,
+        }
+
+        match sel.proto {
+            // ICMP, ICMPv6, MH: the source port is the type, the destination port the code
+            1 | 58 | 135 => write!(
+                f,
+                " type {} code {}",
+                port(sel.sport, sel.sport_mask),
+                port(sel.dport, sel.dport_mask)
+            )?,
+            // GRE: the key is split over the source (high) and destination (low) ports
+            47 if sel.sport_mask != 0 || sel.dport_mask != 0 => write!(
+                f,
+                " key {}",
+                (u32::from(sel.sport) << 16) | u32::from(sel.dport)
+            )?,
+            _ => (),
+        }
+
+        if sel.ifindex != 0 {
+            write!(f, " dev {}", sel.ifindex)?;
+        }
+        Ok(())
+    }
+}
+
+// A selector port (or type, code), as "any", "4500" or "1024/0xfc00".
+fn port(value: u16, mask: u16) -> String {
+    match mask {
+        0 => "any".to_string(),
+        u16::MAX => value.to_string(),
+        _ => format!("{}/{:#06x}", value & mask, mask),
+    }
+}
+
+struct StateDisplay<'a>(&'a state::ModifyMessage);
+
+impl fmt::Display for StateDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = &self.0.user_sa_info;
+
+        writeln!(
+            f,
+            "src {} dst {}",
+            address_to_ip_lossy(&info.saddr, info.family),
+            address_to_ip_lossy(&info.id.daddr, info.family)
+        )?;
+        writeln!(
+            f,
+            "\tproto {} spi {:#010x} reqid {} mode {}",
+            proto_name(info.id.proto),
+            info.id.spi,
+            info.reqid,
+            mode_name(info.mode)
+        )?;
+        writeln!(f, "\treplay-window {}", info.replay_window)?;
+
+        for nla in &self.0.nlas {
+            match nla {
+                XfrmAttrs::AuthenticationAlg(alg) => writeln!(
+                    f,
+                    "\tauth {} {}",
+                    alg_name(&alg.alg_name),
+                    hex(&alg.alg_key)
+                )?,
+                XfrmAttrs::AuthenticationAlgTrunc(alg) => writeln!(
+                    f,
+                    "\tauth-trunc {} {} {}",
+                    alg_name(&alg.alg_name),
+                    hex(&alg.alg_key),
+                    alg.alg_trunc_len
+                )?,
+                XfrmAttrs::EncryptionAlg(alg) => {
+                    writeln!(f, "\tenc {} {}", alg_name(&alg.alg_name), hex(&alg.alg_key))?
+                }
+                XfrmAttrs::EncryptionAlgAead(alg) => writeln!(
+                    f,
+                    "\taead {} {} {}",
+                    alg_name(&alg.alg_name),
+                    hex(&alg.alg_key),
+                    alg.alg_icv_len
+                )?,
+                XfrmAttrs::CompressionAlg(alg) => {
+                    writeln!(f, "\tcomp {}", alg_name(&alg.alg_name))?
+                }
+                XfrmAttrs::EncapsulationTemplate(encap) => writeln!(
+                    f,
+                    "\tencap type {} sport {} dport {} addr {}",
+                    encap.encap_type,
+                    encap.encap_sport,
+                    encap.encap_dport,
+                    address_to_ip_lossy(&encap.encap_oa, info.family)
+                )?,
+                XfrmAttrs::Mark(mark) => writeln!(f, "\tmark {:#x}/{:#x}", mark.value, mark.mask)?,
+                XfrmAttrs::IfId(ifid) => writeln!(f, "\tif_id {:#x}", ifid)?,
+                _ => {}
+            }
+        }
+
+        write!(f, "\tsel ")?;
+        write_selector(f, &info.selector)?;
+        writeln!(f)?;
+
+        write_lifetime(f, &info.lifetime_cfg)?;
+        writeln!(f, "\tlifetime current:")?;
+        writeln!(
+            f,
+            "\t  {}(bytes), {}(packets)",
+            info.lifetime_cur.bytes, info.lifetime_cur.packets
+        )?;
+        writeln!(
+            f,
+            "\t  add {} use {}",
+            info.lifetime_cur.add_time, info.lifetime_cur.use_time
+        )
+    }
+}
+
+struct PolicyDisplay<'a>(&'a policy::ModifyMessage);
+
+impl fmt::Display for PolicyDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = &self.0.user_policy_info;
+
+        write_selector(f, &info.selector)?;
+        writeln!(f)?;
+
+        write!(
+            f,
+            "\tdir {} priority {} index {}",
+            dir_name(info.direction),
+            info.priority,
+            info.index
+        )?;
+        if info.action == 1 {
+            write!(f, " action block")?;
+        }
+        writeln!(f)?;
+
+        for nla in &self.0.nlas {
+            match nla {
+                XfrmAttrs::Mark(mark) => writeln!(f, "\tmark {:#x}/{:#x}", mark.value, mark.mask)?,
+                XfrmAttrs::IfId(ifid) => writeln!(f, "\tif_id {:#x}", ifid)?,
+                XfrmAttrs::Template(templates) => {
+                    for template in templates {
+                        write_template(f, template)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        write_lifetime(f, &info.lifetime_cfg)
+    }
+}
+
+fn write_selector(f: &mut fmt::Formatter<'_>, sel: &Selector) -> fmt::Result {
+    write!(
+        f,
+        "src {}/{} dst {}/{}",
+        address_to_ip_lossy(&sel.saddr, sel.family),
+        sel.prefixlen_s,
+        address_to_ip_lossy(&sel.daddr, sel.family),
+        sel.prefixlen_d
+    )?;
+    if sel.proto != 0 {
+        write!(f, " proto {}", proto_name(sel.proto))?;
+    }
+    if sel.sport_mask != 0 {
+        write!(f, " sport {}", sel.sport)?;
+    }
+    if sel.dport_mask != 0 {
+        write!(f, " dport {}", sel.dport)?;
+    }
+    if sel.ifindex != 0 {
+        write!(f, " dev {}", sel.ifindex)?;
+    }
+    Ok(())
+}
+
+fn write_template(f: &mut fmt::Formatter<'_>, template: &UserTemplate) -> fmt::Result {
+    writeln!(
+        f,
+        "\ttmpl src {} dst {}",
+        address_to_ip_lossy(&template.saddr, template.family),
+        address_to_ip_lossy(&template.id.daddr, template.family)
+    )?;
+    write!(
+        f,
+        "\t\tproto {} spi {:#010x} reqid {} mode {}",
+        proto_name(template.id.proto),
+        template.id.spi,
+        template.reqid,
+        mode_name(template.mode)
+    )?;
+    if template.optional != 0 {
+        write!(f, " level use")?;
+    }
+    writeln!(f)
+}
+
+fn write_lifetime(f: &mut fmt::Formatter<'_>, cfg: &LifetimeConfig) -> fmt::Result {
+    writeln!(f, "\tlifetime config:")?;
+    writeln!(
+        f,
+        "\t  limit: soft {}(bytes), hard {}(bytes)",
+        limit(cfg.soft_byte_limit),
+        limit(cfg.hard_byte_limit)
+    )?;
+    writeln!(
+        f,
+        "\t  limit: soft {}(packets), hard {}(packets)",
+        limit(cfg.soft_packet_limit),
+        limit(cfg.hard_packet_limit)
+    )?;
+    writeln!(
+        f,
+        "\t  expire add: soft {}(sec), hard {}(sec)",
+        cfg.soft_add_expires_seconds, cfg.hard_add_expires_seconds
+    )?;
+    writeln!(
+        f,
+        "\t  expire use: soft {}(sec), hard {}(sec)",
+        cfg.soft_use_expires_seconds, cfg.hard_use_expires_seconds
+    )
+}
+
+// Unlimited is XFRM_INF (all ones)
+fn limit(value: u64) -> String {
+    if value == u64::MAX {
+        "(INF)".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn proto_name(proto: u8) -> String {
+    match proto {
+        1 => "icmp".to_string(),
+        6 => "tcp".to_string(),
+        17 => "udp".to_string(),
+        33 => "dccp".to_string(),
+        43 => "route2".to_string(),
+        47 => "gre".to_string(),
+        50 => "esp".to_string(),
+        51 => "ah".to_string(),
+        58 => "ipv6-icmp".to_string(),
+        60 => "hao".to_string(),
+        108 => "comp".to_string(),
+        132 => "sctp".to_string(),
+        135 => "mh".to_string(),
+        136 => "udplite".to_string(),
+        255 => "ipsec-any".to_string(),
+        _ => proto.to_string(),
+    }
+}
+
+fn mode_name(mode: u8) -> String {
+    match mode {
+        0 => "transport".to_string(),
+        1 => "tunnel".to_string(),
+        2 => "ro".to_string(),
+        3 => "in_trigger".to_string(),
+        4 => "beet".to_string(),
+        5 => "iptfs".to_string(),
+        _ => mode.to_string(),
+    }
+}
+
+fn dir_name(dir: u8) -> String {
+    match dir {
+        0 => "in".to_string(),
+        1 => "out".to_string(),
+        2 => "fwd".to_string(),
+        _ => dir.to_string(),
+    }
+}
+
+// Algorithm names are fixed size, nul terminated C strings.
+fn alg_name(name: &[u8]) -> String {
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+fn hex(key: &[u8]) -> String {
+    let mut s = String::with_capacity(2 + key.len() * 2);
+    s.push_str("0x");
+    for byte in key {
+        s.push_str(&format!("{:02x}", byte));
+    }
+    s
+}
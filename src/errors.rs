@@ -1,10 +1,64 @@
 // SPDX-License-Identifier: MIT
 
+use std::net::IpAddr;
+
 use thiserror::Error;
 
+use crate::SaDirection;
 use netlink_packet_core::{ErrorMessage, NetlinkMessage};
 use netlink_packet_xfrm::XfrmMessage;
 
+pub(crate) const ENOENT: i32 = 2;
+pub(crate) const EINVAL: i32 = 22;
+
+// The errnos the kernel commonly answers xfrm requests with.
+const ERRNO_NAMES: &[(i32, &str)] = &[
+    (1, "EPERM"),
+    (ENOENT, "ENOENT"),
+    (3, "ESRCH"),
+    (4, "EINTR"),
+    (5, "EIO"),
+    (7, "E2BIG"),
+    (11, "EAGAIN"),
+    (12, "ENOMEM"),
+    (13, "EACCES"),
+    (14, "EFAULT"),
+    (16, "EBUSY"),
+    (17, "EEXIST"),
+    (19, "ENODEV"),
+    (EINVAL, "EINVAL"),
+    (28, "ENOSPC"),
+    (34, "ERANGE"),
+    (61, "ENODATA"),
+    (71, "EPROTO"),
+    (75, "EOVERFLOW"),
+    (90, "EMSGSIZE"),
+    (92, "ENOPROTOOPT"),
+    (93, "EPROTONOSUPPORT"),
+    (95, "EOPNOTSUPP"),
+    (97, "EAFNOSUPPORT"),
+    (105, "ENOBUFS"),
+];
+
+/// Return the symbolic name of an errno (e.g. "EEXIST" for 17),
+/// for the errnos the kernel commonly returns to xfrm requests.
+pub fn errno_name(errno: i32) -> Option<&'static str> {
+    ERRNO_NAMES
+        .iter()
+        .find(|(code, _)| *code == errno)
+        .map(|(_, name)| *name)
+}
+
+// e.g. "EEXIST (17): ", so that the errno is readable at a glance in logs.
+fn errno_prefix(msg: &ErrorMessage) -> String {
+    let errno = -msg.raw_code();
+    match errno_name(errno) {
+        Some(name) => format!("{} ({}): ", name, errno),
+        None if errno != 0 => format!("errno {}: ", errno),
+        None => String::new(),
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Error)]
 #[non_exhaustive]
 #[allow(clippy::large_enum_variant)]
@@ -12,18 +66,115 @@ pub enum Error {
     #[error("Received an unexpected message {0:?}")]
     UnexpectedMessage(NetlinkMessage<XfrmMessage>),
 
-    #[error("Received a netlink error message {0}")]
+    #[error("Received a netlink error message {}{0}", errno_prefix(.0))]
     NetlinkError(ErrorMessage),
 
     #[error("A netlink request failed")]
     RequestFailed,
 
+    #[error("The requested object was not found")]
+    NotFound,
+
     #[error("Failed to parse an IP address: {0:?}")]
     InvalidIp(Vec<u8>),
 
     #[error("Failed to parse a network address (IP and mask): {0:?}/{1:?}")]
     InvalidAddress(Vec<u8>, Vec<u8>),
 
+    #[error("Failed to decode a netlink message: {0}")]
+    Decode(String),
+
     #[error("Invalid algorithm string {0}")]
     AlgName(String),
+
+    // Never carries the key itself, so that it can't end up in logs.
+    #[error("Invalid key material at offset {position}: {reason}")]
+    InvalidKey {
+        reason: &'static str,
+        position: usize,
+    },
+
+    #[error("Invalid key length for {alg}: got {got} bits, expected one of {expected:?} bits")]
+    InvalidKeyLength {
+        alg: String,
+        expected: Vec<u32>,
+        got: u32,
+    },
+
+    #[error("Invalid ICV length for {alg}: got {got} bits, expected one of {expected:?} bits")]
+    InvalidIcvLength {
+        alg: String,
+        expected: Vec<u32>,
+        got: u32,
+    },
+
+    #[error("No algorithm set for a state of protocol {0}")]
+    MissingAlgorithm(u8),
+
+    #[error("Encryption algorithm set for a state of protocol {0}, which doesn't encrypt")]
+    UnexpectedAlgorithm(u8),
+
+    #[error("Encapsulation type {0} is not supported by the kernel")]
+    EncapUnsupported(u16),
+
+    #[error("IP-TFS is not supported by the kernel, it requires Linux 6.14 or later")]
+    IpTfsUnsupported,
+
+    #[error("IP-TFS states need a direction, see StateModifyRequest::sa_direction")]
+    MissingSaDirection,
+
+    #[error("IP-TFS parameter {0} doesn't apply to {1:?} states")]
+    IpTfsDirection(&'static str, SaDirection),
+
+    #[error("Invalid xfrm mode {0} for this request")]
+    InvalidMode(u8),
+
+    #[error("IPv4-mapped IPv6 address {0} never matches IPv4 traffic, use the IPv4 address")]
+    MappedAddress(IpAddr),
+
+    #[error("Invalid selector: {reason}")]
+    InvalidSelector { reason: String },
+
+    #[error("Network interface {0:?} not found")]
+    InterfaceNotFound(String),
+
+    #[error("No valid policy index at or above {0}")]
+    InvalidIndex(u32),
+
+    #[error("Policy index {0} can't belong to direction {1}")]
+    IndexDirectionMismatch(u32, u8),
+
+    #[error("The policy direction must be set explicitly")]
+    MissingDirection,
+
+    #[error("Invalid replay window {0} for an ESN state")]
+    InvalidReplayWindow(u32),
+
+    #[error("Invalid SPD hash threshold lbits {0}, rbits {1}")]
+    InvalidHThresh(u8, u8),
+
+    #[error("Invalid SPI {0:#x}, SPIs 0-255 are reserved")]
+    InvalidSpi(u32),
+
+    #[error("Invalid SPI range {0:#x}-{1:#x}, the minimum is above the maximum")]
+    InvalidSpiRange(u32, u32),
+
+    #[error("Invalid CPI {0:#x}, IPComp CPIs must be in the range 0x100-0xffff")]
+    InvalidCpi(u32),
+
+    #[error("SPI {0:#x} is not available, it may already be in use")]
+    SpiUnavailable(u32),
+
+    #[error("The dump was interrupted by a concurrent change, results are inconsistent")]
+    DumpInterrupted,
+}
+
+impl Error {
+    /// Return the (positive) errno carried by a netlink error message, if any.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            Error::NetlinkError(msg) if msg.raw_code() != 0 => Some(-msg.raw_code()),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+
+use crate::{errors::EINVAL, Handle};
+
+/// Kernel features that some requests depend on, see `Handle::kernel_supports`.
+///
+/// Each feature lists the first mainline kernel version supporting it. Distribution
+/// kernels may have backported a feature, or have it disabled in their config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KernelFeature {
+    /// Hardware offload of states (XFRMA_OFFLOAD_DEV), Linux 4.11
+    Offload,
+    /// xfrm interface ids (XFRMA_IF_ID), Linux 4.19
+    InterfaceId,
+    /// TCP encapsulation (RFC 8229), Linux 5.6. Also requires CONFIG_INET_ESPINTCP.
+    TcpEncap,
+    /// Default policy actions (XFRM_MSG_GETDEFAULT/XFRM_MSG_SETDEFAULT), Linux 5.16.
+    /// This one is probed with a get request rather than from the kernel version.
+    PolicyDefault,
+    /// Rate limiting of mapping events (XFRMA_MTIMER_THRESH), Linux 5.17
+    MappingTimer,
+    /// Packet offload of states and policies, Linux 6.2
+    PacketOffload,
+    /// The direction of states (XFRMA_SA_DIR), Linux 6.10
+    SaDirection,
+    /// IP-TFS/AGGFRAG states (XFRMA_IPTFS_*), Linux 6.14
+    IpTfs,
+}
+
+impl KernelFeature {
+    // The first kernel version (major, minor) supporting the feature.
+    fn min_version(self) -> (u32, u32) {
+        match self {
+            KernelFeature::Offload => (4, 11),
+            KernelFeature::InterfaceId => (4, 19),
+            KernelFeature::TcpEncap => (5, 6),
+            KernelFeature::PolicyDefault => (5, 16),
+            KernelFeature::MappingTimer => (5, 17),
+            KernelFeature::PacketOffload => (6, 2),
+            KernelFeature::SaDirection => (6, 10),
+            KernelFeature::IpTfs => (6, 14),
+        }
+    }
+}
+
+// The running kernel version (major, minor), e.g. (6, 1) for "6.1.0-13-amd64".
+fn kernel_version() -> Option<(u32, u32)> {
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let mut parts = release
+        .trim()
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse::<u32>());
+
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
+// Probe the feature with a harmless request, if it has one. Returns None if the
+// feature can't be probed, or if the probe failed for an unrelated reason.
+async fn probe(handle: &Handle, feature: KernelFeature) -> Option<bool> {
+    match feature {
+        // Kernels without default policies reject the unknown message type with EINVAL.
+        KernelFeature::PolicyDefault => {
+            match handle.policy().get_default_action().execute().await {
+                Ok(_) => Some(true),
+                Err(e) if e.errno() == Some(EINVAL) => Some(false),
+                Err(_) => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+pub(crate) async fn kernel_supports(handle: &Handle, feature: KernelFeature) -> bool {
+    let cached = handle.features.lock().unwrap().get(&feature).copied();
+    if let Some(supported) = cached {
+        return supported;
+    }
+
+    let supported = match probe(handle, feature).await {
+        Some(supported) => supported,
+        None => match kernel_version() {
+            Some(version) => version >= feature.min_version(),
+            // Don't cache anything if the version couldn't be read.
+            None => return false,
+        },
+    };
+
+    handle.features.lock().unwrap().insert(feature, supported);
+    supported
+}
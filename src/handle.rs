@@ -1,37 +1,200 @@
 // SPDX-License-Identifier: MIT
 
-use futures::Stream;
+use futures::{channel::mpsc, Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use crate::{Error, PolicyHandle, StateHandle};
+use crate::{features, try_nl, Error, KernelFeature, PolicyHandle, StateHandle};
 use netlink_packet_core::NetlinkMessage;
 use netlink_packet_xfrm::XfrmMessage;
 use netlink_proto::{sys::SocketAddr, ConnectionHandle};
 
+/// A handle to the netlink connection, used to build and send xfrm requests.
+///
+/// Cloning a `Handle` is cheap: it only clones the sending side of the channel to the
+/// connection, which is reference counted internally. Every request builder holds its
+/// own clone, so there's no need to wrap a `Handle` in an `Arc`.
+///
+/// Dropping the last `Handle` (and the connection's message receiver) lets the
+/// `Connection` future complete and close the socket, see `new_connection`.
+///
+/// The `netlink_flags` method of every request builder ORs additional netlink header
+/// flags (NLM_F_*) into the flags of the request. It's an escape hatch for trying flag
+/// combinations the crate doesn't use; the handling of the responses is unchanged.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
-pub struct Handle(ConnectionHandle<XfrmMessage>);
+pub struct Handle {
+    conn: ConnectionHandle<XfrmMessage>,
+    // Results of kernel_supports, shared by every clone.
+    pub(crate) features: Arc<Mutex<HashMap<KernelFeature, bool>>>,
+}
 
 impl Handle {
     pub(crate) fn new(conn: ConnectionHandle<XfrmMessage>) -> Self {
-        Handle(conn)
+        Handle {
+            conn,
+            features: Arc::default(),
+        }
     }
 
+    /// Send a request and return the stream of response messages.
+    ///
+    /// The stream is not filtered: it yields every message received for the request,
+    /// including error/ack messages and message types the crate has no request for yet.
+    /// Build the request by hand (or tweak one from a request's `into_message()`) to
+    /// experiment with such messages.
+    ///
+    /// The sequence number (`nlmsg_seq`) is assigned by the connection when the message
+    /// is sent, replacing the one in the header, so it isn't known when this returns.
+    /// The kernel copies it into every response, so it can be read from the
+    /// `header.sequence_number` of the response messages, e.g. to match them to an
+    /// `nlmon` capture.
+    ///
+    /// Responses are queued by the underlying `netlink_proto` connection in an
+    /// unbounded channel as they are read from the socket, so a slow consumer of a
+    /// large dump does not slow the socket reads down. The whole dump may end up
+    /// buffered in memory. To bound memory usage, consume the stream promptly or
+    /// narrow the dump with a kernel-side filter (e.g. `address_filter`).
+    ///
+    /// The message is queued when this returns, and dropping the stream doesn't
+    /// take it back: the kernel processes the request (and applies any change it
+    /// makes) whether or not the responses are read. For the `async` `execute()`
+    /// methods, the request is only sent once the future is first polled, so dropping
+    /// the future before that sends nothing. After the stream is dropped, the
+    /// connection reads the remaining responses and discards them, and forgets the
+    /// request once its final ACK, error or DONE message arrives. Nothing is leaked,
+    /// and later requests are not affected. Use a `CancelToken` to end a stream early
+    /// from elsewhere, e.g. a timeout.
     pub fn request(
         &mut self,
         message: NetlinkMessage<XfrmMessage>,
     ) -> Result<impl Stream<Item = NetlinkMessage<XfrmMessage>>, Error> {
-        self.0
+        // Only the header fields are logged, the payload may hold key material.
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            message_type = message.payload.message_type(),
+            flags = message.header.flags,
+            "sending xfrm request"
+        );
+
+        let response = self
+            .conn
             .request(message, SocketAddr::new(0, 0))
-            .map_err(|_| Error::RequestFailed)
+            .map_err(|_| {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("xfrm request failed, the connection is closed");
+                Error::RequestFailed
+            })?;
+
+        #[cfg(feature = "tracing")]
+        let response = response.inspect(|msg| {
+            tracing::debug!(
+                message_type = msg.payload.message_type(),
+                flags = msg.header.flags,
+                sequence_number = msg.header.sequence_number,
+                "received xfrm response"
+            )
+        });
+
+        Ok(response)
+    }
+
+    /// Same as `request`, under a name that says the responses are not filtered.
+    pub fn request_raw(
+        &mut self,
+        message: NetlinkMessage<XfrmMessage>,
+    ) -> Result<impl Stream<Item = NetlinkMessage<XfrmMessage>>, Error> {
+        self.request(message)
     }
 
+    /// Send a message without expecting a response.
+    ///
+    /// Like `request`, this only queues the message on the connection: it is written
+    /// to the socket once the `Connection` future is polled again. Returning `Ok` means
+    /// the connection is still alive and accepted the message, not that the kernel
+    /// received it. Keep the connection task running until it has been flushed, e.g.
+    /// don't exit the program right after a fire-and-forget request.
     pub fn notify(&mut self, msg: NetlinkMessage<XfrmMessage>) -> Result<(), Error> {
-        self.0
-            .notify(msg, SocketAddr::new(0, 0))
+        self.send_to(msg, SocketAddr::new(0, 0))
+    }
+
+    /// Send a message to a specific netlink address without expecting a response,
+    /// e.g. to reply to the sender (`nlmsg_pid`) of a message received from a multicast group.
+    ///
+    /// The header flags are sent as given, but the sequence number is always
+    /// assigned by the connection. The same delivery guarantee as `notify` applies.
+    pub fn send_to(
+        &mut self,
+        msg: NetlinkMessage<XfrmMessage>,
+        destination: SocketAddr,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            message_type = msg.payload.message_type(),
+            flags = msg.header.flags,
+            port = destination.port_number(),
+            "sending xfrm notification"
+        );
+
+        self.conn
+            .notify(msg, destination)
             .map_err(|_| Error::RequestFailed)?;
         Ok(())
     }
 
+    /// Open a pipe for sending many requests with bounded concurrency, e.g. to provision
+    /// thousands of states.
+    ///
+    /// Requests pushed into the returned sender (for instance from a builder's
+    /// `into_message()`) are sent as the returned stream is polled, with up to
+    /// `max_in_flight` of them awaiting their ACK at the same time. The stream yields
+    /// the result of each request in the order they were pushed, and ends once the
+    /// sender is dropped and every result has been yielded. The sender applies
+    /// back-pressure when the pipe is full, i.e. when results are not pulled.
+    ///
+    /// Sequence numbers and matching the responses to the requests are handled by the
+    /// connection. The requests need the NLM_F_ACK flag, as set by `into_message()`,
+    /// for their result to be reported. Only the ACK or error is reported, and the
+    /// requests are not validated like their `execute()` counterparts do.
+    pub fn pipe(
+        &self,
+        max_in_flight: usize,
+    ) -> (
+        mpsc::Sender<NetlinkMessage<XfrmMessage>>,
+        impl Stream<Item = Result<(), Error>>,
+    ) {
+        let max_in_flight = max_in_flight.max(1);
+        let (sender, requests) = mpsc::channel(max_in_flight);
+        let handle = self.clone();
+
+        let results = requests
+            .map(move |req| {
+                let mut handle = handle.clone();
+                async move {
+                    let mut response = handle.request(req)?;
+                    while let Some(message) = response.next().await {
+                        try_nl!(message);
+                    }
+                    Ok(())
+                }
+            })
+            .buffered(max_in_flight);
+
+        (sender, results)
+    }
+
+    /// Whether the running kernel supports the feature, so callers can branch up front
+    /// instead of handling EINVAL from requests using it.
+    ///
+    /// Features with a harmless request to try (e.g. `KernelFeature::PolicyDefault`) are
+    /// probed through the connection, the others are checked against the kernel version
+    /// from `/proc/sys/kernel/osrelease`. The result is cached, and shared by the clones
+    /// of the handle, so only the first call for a feature issues a request. Returns
+    /// `false` if the support couldn't be determined.
+    pub async fn kernel_supports(&self, feature: KernelFeature) -> bool {
+        features::kernel_supports(self, feature).await
+    }
+
     /// Create a new handle, specifically for policy requests (equivalent to `ip xfrm policy` commands)
     pub fn policy(&self) -> PolicyHandle {
         PolicyHandle::new(self.clone())
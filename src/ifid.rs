@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+
+/// The id of an xfrm interface (`ip link add ipsec0 type xfrm if_id 42`), sent as
+/// XFRMA_IF_ID. States and policies with an interface id only match traffic routed
+/// through the xfrm interface with the same id, which allows one netdev per tunnel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct XfrmInterfaceId(pub u32);
+
+impl fmt::Display for XfrmInterfaceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u32> for XfrmInterfaceId {
+    fn from(ifid: u32) -> Self {
+        XfrmInterfaceId(ifid)
+    }
+}
+
+impl From<XfrmInterfaceId> for u32 {
+    fn from(ifid: XfrmInterfaceId) -> u32 {
+        ifid.0
+    }
+}
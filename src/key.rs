@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MIT
+
+use crate::Error;
+
+// The error only points at the offending character, the key must not be echoed.
+fn invalid_key(reason: &'static str, position: usize) -> Error {
+    Error::InvalidKey { reason, position }
+}
+
+/// Parse key material given as a hex string (as accepted by iproute2, e.g. `0x0123abcd`).
+///
+/// A leading `0x`/`0X` is stripped. An odd number of hex digits is treated as if it
+/// had a leading zero, so `0xabc` is parsed as `[0x0a, 0xbc]`.
+pub fn parse_hex_key(hex_key: &str) -> Result<Vec<u8>, Error> {
+    let digits = hex_key
+        .strip_prefix("0x")
+        .or_else(|| hex_key.strip_prefix("0X"))
+        .unwrap_or(hex_key);
+    let prefix_len = hex_key.len() - digits.len();
+
+    if digits.is_empty() {
+        return Err(invalid_key("no hex digits", prefix_len));
+    }
+
+    let mut nibbles = Vec::with_capacity(digits.len() + 1);
+    if digits.len() % 2 == 1 {
+        nibbles.push(0);
+    }
+    for (i, c) in digits.char_indices() {
+        let nibble = c
+            .to_digit(16)
+            .ok_or_else(|| invalid_key("not a hex digit", prefix_len + i))?;
+        nibbles.push(nibble as u8);
+    }
+
+    Ok(nibbles
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+/// Parse key material given as a standard (RFC 4648) base64 string.
+/// Trailing `=` padding is optional.
+pub fn parse_base64_key(b64_key: &str) -> Result<Vec<u8>, Error> {
+    let data = b64_key.trim_end_matches('=');
+
+    if data.is_empty() {
+        return Err(invalid_key("no base64 characters", 0));
+    }
+    // A single leftover character can't encode a whole byte.
+    if data.len() % 4 == 1 {
+        return Err(invalid_key("truncated base64", data.len() - 1));
+    }
+
+    let mut key = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+
+    for (i, c) in data.bytes().enumerate() {
+        let val = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(invalid_key("not a base64 character", i)),
+        };
+        acc = (acc << 6) | u32::from(val);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            key.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hex_key_with_prefix() {
+        assert_eq!(
+            parse_hex_key("0x0123abCD").unwrap(),
+            [0x01, 0x23, 0xab, 0xcd]
+        );
+        assert_eq!(parse_hex_key("0X0123").unwrap(), [0x01, 0x23]);
+        assert_eq!(parse_hex_key("0123").unwrap(), [0x01, 0x23]);
+    }
+
+    #[test]
+    fn hex_key_odd_length() {
+        assert_eq!(parse_hex_key("0xabc").unwrap(), [0x0a, 0xbc]);
+        assert_eq!(parse_hex_key("f").unwrap(), [0x0f]);
+    }
+
+    #[test]
+    fn hex_key_invalid() {
+        let secret = "0x0123secret";
+        let err = parse_hex_key(secret).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidKey {
+                reason: "not a hex digit",
+                position: 6
+            }
+        );
+        assert!(!err.to_string().contains("secret"));
+        assert!(!format!("{:?}", err).contains("secret"));
+
+        assert!(matches!(
+            parse_hex_key("0x"),
+            Err(Error::InvalidKey { position: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn base64_key() {
+        assert_eq!(parse_base64_key("AQIDBA==").unwrap(), [1, 2, 3, 4]);
+        assert_eq!(parse_base64_key("AQIDBA").unwrap(), [1, 2, 3, 4]);
+        assert_eq!(parse_base64_key("+/8=").unwrap(), [0xfb, 0xff]);
+    }
+
+    #[test]
+    fn base64_key_invalid() {
+        let err = parse_base64_key("AQI*secret").unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidKey {
+                reason: "not a base64 character",
+                position: 3
+            }
+        );
+        assert!(!format!("{} {:?}", err, err).contains("secret"));
+
+        assert!(matches!(
+            parse_base64_key("AQIDB"),
+            Err(Error::InvalidKey { position: 4, .. })
+        ));
+        assert!(matches!(
+            parse_base64_key("=="),
+            Err(Error::InvalidKey { position: 0, .. })
+        ));
+    }
+}
@@ -5,22 +5,67 @@
 
 #![allow(clippy::module_inception)]
 
+mod address;
+
+mod cancel;
+pub use crate::cancel::*;
+
+mod codec;
+pub use crate::codec::*;
+
 mod connection;
 pub use crate::connection::*;
 
 pub mod constants;
 pub use crate::constants::*;
 
+mod display;
+pub use crate::display::*;
+
 mod errors;
 pub use crate::errors::*;
 
+mod features;
+pub use crate::features::*;
+
 mod handle;
 pub use crate::handle::*;
 
+mod ifid;
+pub use crate::ifid::*;
+
+mod iptfs;
+pub use crate::iptfs::*;
+
+mod key;
+pub use crate::key::*;
+
+mod lifetime;
+pub use crate::lifetime::*;
+
+mod link;
+
 mod macros;
 
+#[cfg(any(test, feature = "mock_socket"))]
+mod mock;
+#[cfg(any(test, feature = "mock_socket"))]
+pub use crate::mock::*;
+
+mod monitor;
+pub use crate::monitor::*;
+
 mod policy;
 pub use crate::policy::*;
 
+mod reqid;
+pub use crate::reqid::*;
+
+mod selector;
+pub use crate::selector::*;
+
+mod spi;
+pub use crate::spi::*;
+
 mod state;
 pub use crate::state::*;
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MIT
+
+use std::time::Duration;
+
+use netlink_packet_xfrm::{Lifetime, LifetimeConfig as XfrmLifetimeConfig, XfrmAttrs};
+
+/// The soft and hard limits of a state or policy, applied with `lifetimes()` on
+/// `StateModifyRequest` and `PolicyModifyRequest`.
+///
+/// Time limits are given as a `Duration` and sent to the kernel in seconds. A sub-second
+/// remainder is rounded up, since a limit of 0 seconds means no limit at all. Limits that
+/// are not set don't expire: time limits default to 0, byte and packet limits to the
+/// kernel's XFRM_INF (`u64::MAX`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LifetimeConfig {
+    soft_add: Duration,
+    hard_add: Duration,
+    soft_use: Duration,
+    hard_use: Duration,
+    soft_bytes: u64,
+    hard_bytes: u64,
+    soft_packets: u64,
+    hard_packets: u64,
+}
+
+impl Default for LifetimeConfig {
+    fn default() -> Self {
+        LifetimeConfig {
+            soft_add: Duration::ZERO,
+            hard_add: Duration::ZERO,
+            soft_use: Duration::ZERO,
+            hard_use: Duration::ZERO,
+            soft_bytes: u64::MAX,
+            hard_bytes: u64::MAX,
+            soft_packets: u64::MAX,
+            hard_packets: u64::MAX,
+        }
+    }
+}
+
+impl LifetimeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expire this long after being added.
+    pub fn time_limit(mut self, soft: Duration, hard: Duration) -> Self {
+        self.soft_add = soft;
+        self.hard_add = hard;
+        self
+    }
+
+    /// Expire this long after being first used.
+    pub fn time_use_limit(mut self, soft: Duration, hard: Duration) -> Self {
+        self.soft_use = soft;
+        self.hard_use = hard;
+        self
+    }
+
+    /// Expire after processing this many bytes.
+    pub fn byte_limit(mut self, soft: u64, hard: u64) -> Self {
+        self.soft_bytes = soft;
+        self.hard_bytes = hard;
+        self
+    }
+
+    /// Expire after processing this many packets.
+    pub fn packet_limit(mut self, soft: u64, hard: u64) -> Self {
+        self.soft_packets = soft;
+        self.hard_packets = hard;
+        self
+    }
+
+    pub(crate) fn apply(&self, cfg: &mut XfrmLifetimeConfig) {
+        cfg.soft_add_expires_seconds = duration_to_secs(self.soft_add);
+        cfg.hard_add_expires_seconds = duration_to_secs(self.hard_add);
+        cfg.soft_use_expires_seconds = duration_to_secs(self.soft_use);
+        cfg.hard_use_expires_seconds = duration_to_secs(self.hard_use);
+        cfg.soft_byte_limit = self.soft_bytes;
+        cfg.hard_byte_limit = self.hard_bytes;
+        cfg.soft_packet_limit = self.soft_packets;
+        cfg.hard_packet_limit = self.hard_packets;
+    }
+}
+
+// The XFRMA_LTIME_VAL attribute (struct xfrm_lifetime_cur) carrying the current
+// lifetime of a state. netlink-packet-xfrm names its variant LifetimeBytes.
+pub(crate) fn lifetime_current_nla(
+    bytes: u64,
+    packets: u64,
+    add_time: u64,
+    use_time: u64,
+) -> XfrmAttrs {
+    XfrmAttrs::LifetimeBytes(Lifetime {
+        bytes,
+        packets,
+        add_time,
+        use_time,
+    })
+}
+
+// Round up, saturating at u64::MAX seconds.
+fn duration_to_secs(duration: Duration) -> u64 {
+    if duration.subsec_nanos() > 0 {
+        duration.as_secs().saturating_add(1)
+    } else {
+        duration.as_secs()
+    }
+}
@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+
+use crate::Error;
+
+// IFNAMSIZ, including the trailing NUL.
+const IFNAMSIZ: usize = 16;
+
+// Equivalent to if_nametoindex(3), reading the index from sysfs so that no libc
+// binding is needed. The index is only valid in the network namespace sysfs was
+// mounted in, which is the current one for a typical process.
+pub(crate) fn ifindex_by_name(name: &str) -> Result<u32, Error> {
+    let not_found = || Error::InterfaceNotFound(name.to_string());
+
+    // Reject anything that isn't a valid interface name before using it in a path.
+    if name.is_empty()
+        || name.len() >= IFNAMSIZ
+        || name == "."
+        || name == ".."
+        || name.contains(|c: char| c == '/' || c == '\0' || c.is_whitespace())
+    {
+        return Err(not_found());
+    }
+
+    let ifindex =
+        fs::read_to_string(format!("/sys/class/net/{}/ifindex", name)).map_err(|_| not_found())?;
+
+    // An ifindex of 0 would mean "any interface" in a selector.
+    match ifindex.trim().parse() {
+        Ok(0) | Err(_) => Err(not_found()),
+        Ok(ifindex) => Ok(ifindex),
+    }
+}
@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: MIT
+
+use std::collections::VecDeque;
+use std::io;
+use std::num::NonZeroI32;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bytes::BufMut;
+
+use crate::{decode_message, encode_message};
+use netlink_packet_core::{
+    DoneMessage, ErrorMessage, NetlinkBuffer, NetlinkHeader, NetlinkMessage, NetlinkPayload,
+    NLM_F_ACK, NLM_F_MULTIPART,
+};
+use netlink_packet_utils::{DecodeError, Parseable};
+use netlink_packet_xfrm::XfrmMessage;
+use netlink_proto::sys::{AsyncSocket, SocketAddr};
+use netlink_sys::Socket;
+
+type Responder =
+    Box<dyn FnMut(&NetlinkMessage<XfrmMessage>) -> Vec<NetlinkMessage<XfrmMessage>> + Send>;
+
+/// A socket that never talks to the kernel, for testing requests without root.
+/// Requires the `mock_socket` feature.
+///
+/// Every message sent through it is recorded, and answered by a responder set
+/// with `MockController::respond`. Use it with `new_connection_with_socket`:
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use xfrmnetlink::{new_connection_with_socket, MockController, MockSocket};
+///
+/// let (mut connection, handle, _) = new_connection_with_socket::<MockSocket>()?;
+/// let mock = connection.socket_mut().controller();
+/// tokio::spawn(connection);
+///
+/// // Simulate the kernel refusing every request with EPERM.
+/// mock.respond(|_| vec![MockController::error(1)]);
+///
+/// let result = handle.state().flush().execute().await;
+/// assert_eq!(result.unwrap_err().errno(), Some(1));
+/// assert_eq!(mock.sent().len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockSocket {
+    socket: Socket,
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    sent: Vec<NetlinkMessage<XfrmMessage>>,
+    responder: Option<Responder>,
+    pending: VecDeque<Vec<u8>>,
+    waker: Option<Waker>,
+}
+
+/// Inspects and controls a `MockSocket`, from outside of the spawned connection.
+#[derive(Clone)]
+pub struct MockController(Arc<Mutex<MockState>>);
+
+impl MockSocket {
+    /// Get a controller for this socket. Call it before the connection is spawned.
+    pub fn controller(&self) -> MockController {
+        MockController(self.state.clone())
+    }
+}
+
+impl MockController {
+    /// The messages sent so far, in order. Requests that netlink-packet-xfrm can't
+    /// parse back, e.g. dumps, have their payload as a raw `XfrmMessage::Other`.
+    pub fn sent(&self) -> Vec<NetlinkMessage<XfrmMessage>> {
+        self.0.lock().unwrap().sent.clone()
+    }
+
+    /// Answer each sent message with the messages returned by `responder`. Their
+    /// sequence number is set to the one of the request, and the multipart flag is
+    /// set when there is more than one (a DONE message is appended for those).
+    ///
+    /// Without a responder, requests with NLM_F_ACK are acknowledged and others
+    /// get no answer.
+    pub fn respond<F>(&self, responder: F)
+    where
+        F: FnMut(&NetlinkMessage<XfrmMessage>) -> Vec<NetlinkMessage<XfrmMessage>> + Send + 'static,
+    {
+        self.0.lock().unwrap().responder = Some(Box::new(responder));
+    }
+
+    /// A netlink error message with the given (positive) errno.
+    pub fn error(errno: i32) -> NetlinkMessage<XfrmMessage> {
+        let mut error = ErrorMessage::default();
+        error.code = NonZeroI32::new(-errno);
+        NetlinkMessage::new(NetlinkHeader::default(), NetlinkPayload::Error(error))
+    }
+
+    /// A netlink acknowledgement, i.e. an error message without an error.
+    pub fn ack() -> NetlinkMessage<XfrmMessage> {
+        NetlinkMessage::new(
+            NetlinkHeader::default(),
+            NetlinkPayload::Error(ErrorMessage::default()),
+        )
+    }
+}
+
+impl MockState {
+    fn handle_request(&mut self, buf: &[u8]) -> io::Result<()> {
+        let request = decode_request(buf)?;
+
+        let mut responses = match &mut self.responder {
+            Some(responder) => responder(&request),
+            None if request.header.flags & NLM_F_ACK == NLM_F_ACK => {
+                vec![MockController::ack()]
+            }
+            None => Vec::new(),
+        };
+
+        let multipart = responses.len() > 1;
+        if multipart {
+            responses.push(NetlinkMessage::new(
+                NetlinkHeader::default(),
+                NetlinkPayload::Done(DoneMessage::default()),
+            ));
+        }
+
+        for mut response in responses {
+            response.header.sequence_number = request.header.sequence_number;
+            response.header.port_number = request.header.port_number;
+            if multipart {
+                response.header.flags |= NLM_F_MULTIPART;
+            }
+            self.pending.push_back(encode_message(&mut response));
+        }
+        self.sent.push(request);
+
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>> {
+        match self.pending.pop_front() {
+            Some(buf) => Poll::Ready(buf),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Some requests, e.g. dumps, have a layout that netlink-packet-xfrm can only emit.
+// Those are recorded as a raw XfrmMessage::Other with the header parsed.
+fn decode_request(buf: &[u8]) -> io::Result<NetlinkMessage<XfrmMessage>> {
+    if let Ok(request) = decode_message(buf) {
+        return Ok(request);
+    }
+
+    let invalid = |e: DecodeError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let buffer = NetlinkBuffer::new_checked(buf).map_err(invalid)?;
+    let header = NetlinkHeader::parse(&buffer).map_err(invalid)?;
+    let payload = XfrmMessage::Other((header.message_type, buffer.payload().to_vec()));
+    Ok(NetlinkMessage::new(
+        header,
+        NetlinkPayload::InnerMessage(payload),
+    ))
+}
+
+impl AsyncSocket for MockSocket {
+    fn socket_ref(&self) -> &Socket {
+        &self.socket
+    }
+
+    fn socket_mut(&mut self) -> &mut Socket {
+        &mut self.socket
+    }
+
+    // Opening a netlink socket doesn't need any privilege, it is only
+    // there to satisfy socket_ref/socket_mut and is never used for I/O.
+    fn new(protocol: isize) -> io::Result<Self> {
+        Ok(MockSocket {
+            socket: Socket::new(protocol)?,
+            state: Arc::default(),
+        })
+    }
+
+    fn poll_send(&self, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let result = self.state.lock().unwrap().handle_request(buf);
+        Poll::Ready(result.map(|_| buf.len()))
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        _addr: &SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf)
+    }
+
+    fn poll_recv<B>(&self, cx: &mut Context<'_>, buf: &mut B) -> Poll<io::Result<()>>
+    where
+        B: BufMut,
+    {
+        self.poll_recv_from(cx, buf).map_ok(|_| ())
+    }
+
+    fn poll_recv_from<B>(&self, cx: &mut Context<'_>, buf: &mut B) -> Poll<io::Result<SocketAddr>>
+    where
+        B: BufMut,
+    {
+        self.state.lock().unwrap().poll_pending(cx).map(|pending| {
+            buf.put_slice(&pending);
+            Ok(SocketAddr::new(0, 0))
+        })
+    }
+
+    fn poll_recv_from_full(&self, cx: &mut Context<'_>) -> Poll<io::Result<(Vec<u8>, SocketAddr)>> {
+        self.state
+            .lock()
+            .unwrap()
+            .poll_pending(cx)
+            .map(|pending| Ok((pending, SocketAddr::new(0, 0))))
+    }
+}
+
+// A handle on a spawned connection over a `MockSocket`, for the tests.
+#[cfg(test)]
+pub(crate) fn mock_handle() -> (crate::Handle, MockController) {
+    let (mut connection, handle, _) = crate::new_connection_with_socket::<MockSocket>().unwrap();
+    let mock = connection.socket_mut().controller();
+    tokio::spawn(connection);
+    (handle, mock)
+}
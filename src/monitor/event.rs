@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: MIT
+
+use futures::{
+    channel::mpsc::UnboundedReceiver,
+    future,
+    stream::{Stream, StreamExt},
+};
+
+use crate::{Error, XfrmMapping, XfrmReport};
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
+use netlink_packet_xfrm::{
+    monitor::{AcquireMessage, ExpireMessage, MigrateMessage},
+    policy, state, XfrmMessage,
+};
+use netlink_proto::sys::SocketAddr;
+
+/// An xfrm event received from the kernel multicast groups, see `XfrmMonitor`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum XfrmEvent {
+    /// A state was added or updated (XFRM_MSG_NEWSA/XFRM_MSG_UPDSA)
+    SaAdded(state::ModifyMessage),
+    /// A state was deleted (XFRM_MSG_DELSA)
+    SaDeleted(state::DelGetMessage),
+    /// A state reached its soft or hard lifetime limit (XFRM_MSG_EXPIRE).
+    /// After a hard expire, the kernel has already deleted the state.
+    Expired { hard: bool, message: ExpireMessage },
+    /// Outbound traffic matched a policy with no state, the key manager should
+    /// negotiate one (XFRM_MSG_ACQUIRE)
+    Acquire(AcquireMessage),
+    /// States and policies are migrated to new endpoints (XFRM_MSG_MIGRATE)
+    Migrate(MigrateMessage),
+    /// The NAT in front of a peer rebound (XFRM_MSG_MAPPING)
+    Mapping(XfrmMapping),
+    /// A policy was added or updated (XFRM_MSG_NEWPOLICY/XFRM_MSG_UPDPOLICY)
+    PolicyAdded(policy::ModifyMessage),
+    /// A policy was deleted (XFRM_MSG_DELPOLICY)
+    PolicyDeleted(policy::DelGetMessage),
+    /// A report event (XFRM_MSG_REPORT)
+    Report(XfrmReport),
+    /// Any other xfrm message, e.g. a flush or a policy expire
+    Other(XfrmMessage),
+}
+
+impl From<XfrmMessage> for XfrmEvent {
+    fn from(msg: XfrmMessage) -> Self {
+        match msg {
+            XfrmMessage::AddSa(m) | XfrmMessage::UpdateSa(m) => XfrmEvent::SaAdded(m),
+            XfrmMessage::DeleteSa(m) => XfrmEvent::SaDeleted(m),
+            XfrmMessage::Expire(m) => XfrmEvent::Expired {
+                hard: m.expire.hard != 0,
+                message: m,
+            },
+            XfrmMessage::Acquire(m) => XfrmEvent::Acquire(m),
+            XfrmMessage::Migrate(m) => XfrmEvent::Migrate(m),
+            XfrmMessage::Mapping(m) => XfrmEvent::Mapping(XfrmMapping::from(m)),
+            XfrmMessage::AddPolicy(m) | XfrmMessage::UpdatePolicy(m) => XfrmEvent::PolicyAdded(m),
+            XfrmMessage::DeletePolicy(m) => XfrmEvent::PolicyDeleted(m),
+            XfrmMessage::Report(m) => XfrmEvent::Report(XfrmReport::from(m)),
+            other => XfrmEvent::Other(other),
+        }
+    }
+}
+
+// Decode one message from the multicast groups. Netlink control messages
+// (noop, done, overrun) carry no event and are skipped.
+fn decode(msg: NetlinkMessage<XfrmMessage>) -> Option<Result<XfrmEvent, Error>> {
+    match msg.payload {
+        NetlinkPayload::InnerMessage(msg) => Some(Ok(XfrmEvent::from(msg))),
+        NetlinkPayload::Error(err) => Some(Err(Error::NetlinkError(err))),
+        _ => None,
+    }
+}
+
+/// Typed xfrm events from the messages received by a connection, i.e. the receiver
+/// returned by `new_connection`.
+///
+/// The connection's socket must be bound to the XFRMNLGRP_* groups of interest
+/// before it is spawned, see `XfrmGroups` and the `monitor` example.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use futures::stream::TryStreamExt;
+/// use netlink_proto::sys::{AsyncSocket, SocketAddr};
+/// use xfrmnetlink::{new_connection, XfrmEvent, XfrmGroups, XfrmMonitor};
+///
+/// let (mut connection, _, messages) = new_connection()?;
+/// let groups = XfrmGroups::EXPIRE | XfrmGroups::ACQUIRE;
+/// connection
+///     .socket_mut()
+///     .socket_mut()
+///     .bind(&SocketAddr::new(0, groups.bits()))?;
+/// tokio::spawn(connection);
+///
+/// let mut events = Box::pin(XfrmMonitor::new(messages).events());
+/// while let Some(event) = events.try_next().await? {
+///     if let XfrmEvent::Expired { hard: false, .. } = event {
+///         // rekey
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct XfrmMonitor {
+    messages: UnboundedReceiver<(NetlinkMessage<XfrmMessage>, SocketAddr)>,
+}
+
+impl XfrmMonitor {
+    pub fn new(messages: UnboundedReceiver<(NetlinkMessage<XfrmMessage>, SocketAddr)>) -> Self {
+        XfrmMonitor { messages }
+    }
+
+    /// The stream of events. It ends when the connection is closed.
+    pub fn events(self) -> impl Stream<Item = Result<XfrmEvent, Error>> {
+        self.messages
+            .filter_map(|(msg, _)| future::ready(decode(msg)))
+    }
+}
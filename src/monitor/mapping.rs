@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+
+use crate::address::address_to_ip_lossy;
+use netlink_packet_xfrm::monitor::MappingMessage;
+
+/// A decoded XFRM_MSG_MAPPING event, sent by the kernel to the XFRMNLGRP_MAPPING group
+/// when the NAT in front of a peer rebinds, i.e. the source address or port of the UDP
+/// encapsulated packets of an inbound state changes. The key manager should update
+/// the peer's address and port, e.g. on the outbound state and in IKE.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct XfrmMapping {
+    /// The destination address of the state (the local address)
+    pub dst_addr: IpAddr,
+    /// The SPI of the state
+    pub spi: u32,
+    /// The xfrm protocol (IPPROTO_*) of the state
+    pub proto: u8,
+    pub reqid: u32,
+    /// The peer address and encapsulation source port before the change
+    pub old_src_addr: IpAddr,
+    pub old_sport: u16,
+    /// The peer address and encapsulation source port the packets now come from
+    pub new_src_addr: IpAddr,
+    pub new_sport: u16,
+}
+
+impl From<&MappingMessage> for XfrmMapping {
+    fn from(msg: &MappingMessage) -> Self {
+        let mapping = &msg.map;
+        let family = mapping.id.family;
+
+        XfrmMapping {
+            dst_addr: address_to_ip_lossy(&mapping.id.daddr, family),
+            spi: mapping.id.spi,
+            proto: mapping.id.proto,
+            reqid: mapping.reqid,
+            old_src_addr: address_to_ip_lossy(&mapping.old_saddr, family),
+            old_sport: mapping.old_sport,
+            new_src_addr: address_to_ip_lossy(&mapping.new_saddr, family),
+            new_sport: mapping.new_sport,
+        }
+    }
+}
+
+impl From<MappingMessage> for XfrmMapping {
+    fn from(msg: MappingMessage) -> Self {
+        XfrmMapping::from(&msg)
+    }
+}
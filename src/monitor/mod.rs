@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: MIT
+
+mod event;
+pub use self::event::*;
+
+mod mapping;
+pub use self::mapping::*;
+
+mod report;
+pub use self::report::*;
+
+// The fixtures are in host byte order, see test.rs.
+#[cfg(all(test, target_endian = "little"))]
+mod test;
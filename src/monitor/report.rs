@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: MIT
+
+use netlink_packet_xfrm::{monitor::ReportMessage, Address, Selector, XfrmAttrs};
+
+/// A decoded XFRM_MSG_REPORT event, sent by the kernel to the XFRMNLGRP_REPORT group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct XfrmReport {
+    /// The xfrm protocol (IPPROTO_*) of the state that triggered the report
+    pub proto: u8,
+    /// The selector of the traffic that triggered the report
+    pub selector: Selector,
+    /// The care-of address, only sent for route optimization (route2/hao) states
+    pub care_of_addr: Option<Address>,
+}
+
+impl From<&ReportMessage> for XfrmReport {
+    fn from(msg: &ReportMessage) -> Self {
+        let care_of_addr = msg.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::CareOfAddr(addr) => Some(*addr),
+            _ => None,
+        });
+
+        XfrmReport {
+            proto: msg.report.proto,
+            selector: msg.report.selector,
+            care_of_addr,
+        }
+    }
+}
+
+impl From<ReportMessage> for XfrmReport {
+    fn from(msg: ReportMessage) -> Self {
+        XfrmReport::from(&msg)
+    }
+}
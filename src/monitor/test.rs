@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use futures::{channel::mpsc, stream::StreamExt};
+
+use crate::{
+    decode_message, encode_message, MockController, XfrmEvent, XfrmGroups, XfrmMapping,
+    XfrmMonitor, XfrmReport,
+};
+use netlink_packet_core::{DoneMessage, NetlinkHeader, NetlinkMessage, NetlinkPayload};
+use netlink_packet_xfrm::{
+    monitor::{AcquireMessage, ExpireMessage},
+    policy, state, Address, XfrmMessage, AF_INET, IPPROTO_ESP, IPPROTO_TCP,
+};
+use netlink_proto::sys::SocketAddr;
+
+// Captures of the kernel multicast messages. The integers are in host byte order,
+// so these only hold on little endian hosts.
+
+// An XFRM_MSG_REPORT for TCP traffic from 10.0.0.0/24 to 10.0.1.0/24 on an ESP
+// state, with the care-of address 2001:db8::1.
+const REPORT: &[&str] = &[
+    // nlmsghdr
+    "60000000200000000000000000000000",
+    // xfrm_user_report: proto
+    "32000000",
+    // selector
+    "0a0001000000000000000000000000000a000000000000000000000000000000",
+    "000000000000000002001818060000000000000000000000",
+    // XFRMA_COADDR
+    "14000e0020010db8000000000000000000000001",
+];
+
+// An XFRM_MSG_MAPPING for the ESP state 0x1000 to 192.0.2.2 (reqid 7), whose peer
+// moved from 198.51.100.1:4500 to 198.51.100.9:31000.
+const MAPPING: &[&str] = &[
+    // nlmsghdr
+    "50000000260000000000000000000000",
+    // xfrm_user_mapping: id
+    "c0000202000000000000000000000000000010000200320007000000",
+    // old_saddr, new_saddr
+    "c6336401000000000000000000000000c6336409000000000000000000000000",
+    // old_sport, new_sport
+    "11947918",
+];
+
+fn decode(lines: &[&str]) -> XfrmMessage {
+    match raw(lines).payload {
+        NetlinkPayload::InnerMessage(msg) => msg,
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+fn raw(lines: &[&str]) -> NetlinkMessage<XfrmMessage> {
+    decode_message(&hex::decode(lines.concat()).unwrap()).unwrap()
+}
+
+// The message as it is received, i.e. encoded by the kernel then decoded.
+fn received(msg: XfrmMessage) -> NetlinkMessage<XfrmMessage> {
+    decode_message(&encode_message(&mut NetlinkMessage::from(msg))).unwrap()
+}
+
+#[test]
+fn report_fixture() {
+    let report = match decode(REPORT) {
+        XfrmMessage::Report(msg) => XfrmReport::from(msg),
+        msg => panic!("unexpected message {:?}", msg),
+    };
+
+    assert_eq!(report.proto, IPPROTO_ESP);
+    assert_eq!(
+        report.selector.saddr,
+        Address::from_ip(&IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)))
+    );
+    assert_eq!(
+        report.selector.daddr,
+        Address::from_ip(&IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)))
+    );
+    assert_eq!(
+        (report.selector.prefixlen_s, report.selector.prefixlen_d),
+        (24, 24)
+    );
+    assert_eq!(report.selector.proto, IPPROTO_TCP);
+    assert_eq!(report.selector.family, AF_INET);
+    assert_eq!(
+        report.care_of_addr,
+        Some(Address::from_ip(&IpAddr::V6(Ipv6Addr::new(
+            0x2001, 0xdb8, 0, 0, 0, 0, 0, 1
+        ))))
+    );
+}
+
+#[test]
+fn mapping_fixture() {
+    let mapping = match decode(MAPPING) {
+        XfrmMessage::Mapping(msg) => XfrmMapping::from(msg),
+        msg => panic!("unexpected message {:?}", msg),
+    };
+
+    assert_eq!(
+        mapping,
+        XfrmMapping {
+            dst_addr: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            spi: 0x1000,
+            proto: IPPROTO_ESP,
+            reqid: 7,
+            old_src_addr: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1)),
+            old_sport: 4500,
+            new_src_addr: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 9)),
+            new_sport: 31000,
+        }
+    );
+}
+
+#[tokio::test]
+async fn monitor_yields_typed_events() {
+    let mut expire = ExpireMessage::default();
+    expire.expire.hard = 1;
+
+    let messages = vec![
+        received(XfrmMessage::AddSa(state::ModifyMessage::default())),
+        received(XfrmMessage::UpdateSa(state::ModifyMessage::default())),
+        received(XfrmMessage::DeleteSa(state::DelGetMessage::default())),
+        received(XfrmMessage::Expire(expire)),
+        received(XfrmMessage::Expire(ExpireMessage::default())),
+        received(XfrmMessage::Acquire(AcquireMessage::default())),
+        raw(MAPPING),
+        received(XfrmMessage::AddPolicy(policy::ModifyMessage::default())),
+        received(XfrmMessage::DeletePolicy(policy::DelGetMessage::default())),
+        // Control messages carry no event.
+        NetlinkMessage::new(
+            NetlinkHeader::default(),
+            NetlinkPayload::Done(DoneMessage::default()),
+        ),
+        raw(REPORT),
+        MockController::error(105),
+    ];
+
+    let (tx, rx) = mpsc::unbounded();
+    let groups = XfrmGroups::all().bits();
+    for msg in messages {
+        tx.unbounded_send((msg, SocketAddr::new(0, groups)))
+            .unwrap();
+    }
+    drop(tx);
+
+    let events: Vec<_> = XfrmMonitor::new(rx).events().collect().await;
+    assert_eq!(events.len(), 11);
+
+    let mut events = events.into_iter();
+    let mut next = || events.next().unwrap();
+    assert!(matches!(next(), Ok(XfrmEvent::SaAdded(_))));
+    assert!(matches!(next(), Ok(XfrmEvent::SaAdded(_))));
+    assert!(matches!(next(), Ok(XfrmEvent::SaDeleted(_))));
+    assert!(matches!(next(), Ok(XfrmEvent::Expired { hard: true, .. })));
+    assert!(matches!(next(), Ok(XfrmEvent::Expired { hard: false, .. })));
+    assert!(matches!(next(), Ok(XfrmEvent::Acquire(_))));
+    match next() {
+        Ok(XfrmEvent::Mapping(mapping)) => assert_eq!(mapping.new_sport, 31000),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(matches!(next(), Ok(XfrmEvent::PolicyAdded(_))));
+    assert!(matches!(next(), Ok(XfrmEvent::PolicyDeleted(_))));
+    match next() {
+        Ok(XfrmEvent::Report(report)) => assert_eq!(report.proto, IPPROTO_ESP),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(next().unwrap_err().errno(), Some(105));
+}
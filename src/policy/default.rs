@@ -6,29 +6,49 @@ use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
 use netlink_packet_xfrm::{policy::DefaultMessage, XfrmMessage};
 
 /// A request to get the default xfrm action for input, output, forward policies. This is equivalent to the `ip xfrm policy getdefault` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicyGetDefaultRequest {
     handle: Handle,
     message: DefaultMessage,
+    netlink_flags: u16,
 }
 
 impl PolicyGetDefaultRequest {
     pub(crate) fn new(handle: Handle) -> Self {
         let message = DefaultMessage::default();
 
-        PolicyGetDefaultRequest { handle, message }
+        PolicyGetDefaultRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
-    /// Execute the request
-    pub async fn execute(self) -> Result<DefaultMessage, Error> {
+    /// Extra netlink header flags for the default action get, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicyGetDefaultRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::GetPolicyDefault(message));
-
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<DefaultMessage, Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -45,10 +65,12 @@ impl PolicyGetDefaultRequest {
 }
 
 /// A request to set the default xfrm action for input, output, forward policies. This is equivalent to the `ip xfrm policy setdefault` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicySetDefaultRequest {
     handle: Handle,
     message: DefaultMessage,
+    netlink_flags: u16,
 }
 
 impl PolicySetDefaultRequest {
@@ -59,18 +81,37 @@ impl PolicySetDefaultRequest {
         message.user_policy.forward = fwd_act;
         message.user_policy.output = out_act;
 
-        PolicySetDefaultRequest { handle, message }
+        PolicySetDefaultRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
+    }
+
+    /// Extra netlink header flags for the default action update, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
     }
 
-    /// Execute the request
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicySetDefaultRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::SetPolicyDefault(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -81,14 +122,11 @@ impl PolicySetDefaultRequest {
     }
 
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let PolicySetDefaultRequest {
-            mut handle,
-            message,
-        } = self;
-
-        let mut req = NetlinkMessage::from(XfrmMessage::SetPolicyDefault(message));
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
@@ -3,17 +3,22 @@
 use futures::stream::StreamExt;
 use std::net::IpAddr;
 
-use crate::{try_nl, Error, Handle};
+use crate::{
+    address::check_selector, link::ifindex_by_name, try_nl, Error, Handle, SelectorBuilder,
+    XfrmInterfaceId,
+};
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
 use netlink_packet_xfrm::{
-    policy::DelGetMessage, Mark, SecurityCtx, UserPolicyType, XfrmAttrs, XfrmMessage,
+    policy::DelGetMessage, Mark, SecurityCtx, Selector, UserPolicyType, XfrmAttrs, XfrmMessage,
 };
 
 /// A request to delete xfrm policies. This is equivalent to the `ip xfrm policy delete` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicyDeleteRequest {
     handle: Handle,
     message: DelGetMessage,
+    netlink_flags: u16,
 }
 
 impl PolicyDeleteRequest {
@@ -35,7 +40,11 @@ impl PolicyDeleteRequest {
             .selector
             .destination_prefix(&dst_addr, dst_prefix_len);
 
-        PolicyDeleteRequest { handle, message }
+        PolicyDeleteRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     pub(crate) fn new_index(handle: Handle, index: u32) -> Self {
@@ -43,7 +52,11 @@ impl PolicyDeleteRequest {
 
         message.user_policy_id.index = index;
 
-        PolicyDeleteRequest { handle, message }
+        PolicyDeleteRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     pub fn direction(mut self, direction: u8) -> Self {
@@ -84,6 +97,10 @@ impl PolicyDeleteRequest {
         self.message.nlas.push(XfrmAttrs::IfId(ifid));
         self
     }
+    /// Same as `ifid`, with a typed xfrm interface id.
+    pub fn interface_id(self, ifid: XfrmInterfaceId) -> Self {
+        self.ifid(ifid.0)
+    }
     pub fn mark(mut self, mark: u32, mask: u32) -> Self {
         self.message
             .nlas
@@ -91,51 +108,80 @@ impl PolicyDeleteRequest {
         self
     }
 
-    pub fn selector_protocol(mut self, proto: u8) -> Self {
-        self.message.user_policy_id.selector.proto = proto;
+    /// Replace the whole selector, e.g. with one built with `SelectorBuilder`.
+    pub fn selector(mut self, selector: impl Into<Selector>) -> Self {
+        self.message.user_policy_id.selector = selector.into();
         self
     }
-    pub fn selector_protocol_src_port(mut self, port: u16) -> Self {
-        self.message.user_policy_id.selector.sport = port;
-        self.message.user_policy_id.selector.sport_mask = u16::MAX;
-        self
+    pub fn selector_protocol(self, proto: u8) -> Self {
+        self.update_selector(|sel| sel.protocol(proto))
     }
-    pub fn selector_protocol_dst_port(mut self, port: u16) -> Self {
-        self.message.user_policy_id.selector.dport = port;
-        self.message.user_policy_id.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_src_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port(port))
     }
-    pub fn selector_protocol_type(mut self, proto_type: u8) -> Self {
-        self.message.user_policy_id.selector.sport = proto_type as u16;
-        self.message.user_policy_id.selector.sport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_dst_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port(port))
     }
-    pub fn selector_protocol_code(mut self, proto_code: u8) -> Self {
-        self.message.user_policy_id.selector.dport = proto_code as u16;
-        self.message.user_policy_id.selector.dport_mask = u16::MAX;
-        self
+    // See SelectorBuilder::protocol_src_port_masked for which ranges can be matched.
+    pub fn selector_protocol_src_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port_masked(port, mask))
+    }
+    pub fn selector_protocol_dst_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port_masked(port, mask))
+    }
+    pub fn selector_protocol_type(self, proto_type: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_type(proto_type))
+    }
+    pub fn selector_protocol_code(self, proto_code: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_code(proto_code))
+    }
+    pub fn selector_protocol_gre_key(self, gre_key: u32) -> Self {
+        self.update_selector(|sel| sel.protocol_gre_key(gre_key))
     }
-    pub fn selector_protocol_gre_key(mut self, gre_key: u32) -> Self {
-        self.message.user_policy_id.selector.sport = (gre_key >> 16) as u16;
-        self.message.user_policy_id.selector.sport_mask = u16::MAX;
-        self.message.user_policy_id.selector.dport = (gre_key & 0xffff) as u16;
-        self.message.user_policy_id.selector.dport_mask = u16::MAX;
+    pub fn selector_dev_id(self, id: u32) -> Self {
+        self.update_selector(|sel| sel.dev_id(id))
+    }
+    /// Same as `selector_dev_id`, looking up the index of the named interface.
+    /// Fails with `Error::InterfaceNotFound` if there is no such interface, rather
+    /// than leaving an ifindex of 0, which would match any interface.
+    pub fn selector_dev(self, name: &str) -> Result<Self, Error> {
+        Ok(self.selector_dev_id(ifindex_by_name(name)?))
+    }
+    fn update_selector<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(SelectorBuilder) -> SelectorBuilder,
+    {
+        self.message.user_policy_id.selector =
+            f(self.message.user_policy_id.selector.into()).build();
         self
     }
-    pub fn selector_dev_id(mut self, id: u32) -> Self {
-        self.message.user_policy_id.selector.ifindex = id as i32;
+
+    /// Extra netlink header flags for the delete, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
         self
     }
 
-    /// Execute the request.
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicyDeleteRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::DeletePolicy(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        check_selector(&self.message.user_policy_id.selector)?;
+
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -146,14 +192,13 @@ impl PolicyDeleteRequest {
     }
 
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let PolicyDeleteRequest {
-            mut handle,
-            message,
-        } = self;
+        check_selector(&self.message.user_policy_id.selector)?;
 
-        let mut req = NetlinkMessage::from(XfrmMessage::DeletePolicy(message));
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
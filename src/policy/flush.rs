@@ -7,17 +7,28 @@ use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
 use netlink_packet_xfrm::{policy::FlushMessage, UserPolicyType, XfrmAttrs, XfrmMessage};
 
 /// A request to flush xfrm policies. This is equivalent to the `ip xfrm policy flush` command.
+///
+/// Only the SPD policies (in, out and fwd, of the given type) are flushed. Per-socket
+/// policies, set with the IP_XFRM_POLICY/IPV6_XFRM_POLICY socket options, are attached
+/// to their socket and are never removed by a flush; they go away when the socket is
+/// closed or its policy is cleared. There is no flag to change this in either direction.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicyFlushRequest {
     handle: Handle,
     message: FlushMessage,
+    netlink_flags: u16,
 }
 
 impl PolicyFlushRequest {
     pub(crate) fn new(handle: Handle) -> Self {
         let message = FlushMessage::default();
 
-        PolicyFlushRequest { handle, message }
+        PolicyFlushRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     // Use XFRM_POLICY_TYPE_MAIN or XFRM_POLICY_TYPE_SUB.
@@ -33,15 +44,30 @@ impl PolicyFlushRequest {
         self
     }
 
-    /// Execute the request.
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Extra netlink header flags for the flush, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicyFlushRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::FlushPolicy(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
         while let Some(message) = response.next().await {
@@ -51,14 +77,11 @@ impl PolicyFlushRequest {
     }
 
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let PolicyFlushRequest {
-            mut handle,
-            message,
-        } = self;
-
-        let mut req = NetlinkMessage::from(XfrmMessage::FlushPolicy(message));
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
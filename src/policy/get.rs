@@ -3,24 +3,32 @@
 //use futures::stream::StreamExt;
 use futures::{
     future::{self, Either},
-    stream::{StreamExt, TryStream},
+    stream::{StreamExt, TryStream, TryStreamExt},
     FutureExt,
 };
 use std::net::IpAddr;
 
-use crate::{try_xfrmnl, Error, Handle};
-use netlink_packet_core::{NetlinkMessage, NLM_F_DUMP, NLM_F_REQUEST};
+use crate::{
+    address::{check_selector, selector_match},
+    link::ifindex_by_name,
+    try_xfrmnl, Error, Handle, SelectorBuilder, XfrmInterfaceId,
+};
+use netlink_packet_core::{NetlinkMessage, NLM_F_DUMP, NLM_F_DUMP_INTR, NLM_F_REQUEST};
 use netlink_packet_xfrm::{
     policy::{DelGetMessage, ModifyMessage},
-    Mark, SecurityCtx, UserPolicyType, XfrmAttrs, XfrmMessage,
+    Mark, SecurityCtx, Selector, UserPolicyType, XfrmAttrs, XfrmMessage,
 };
 
 /// A request to get xfrm policies. This is equivalent to the `ip xfrm policy get` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicyGetRequest {
     handle: Handle,
     message: DelGetMessage,
+    netlink_flags: u16,
     dump: bool,
+    address_filter: Option<(IpAddr, u8, IpAddr, u8)>,
+    priority_range: Option<(u32, u32)>,
 }
 
 impl PolicyGetRequest {
@@ -45,7 +53,10 @@ impl PolicyGetRequest {
         PolicyGetRequest {
             handle,
             message,
+            netlink_flags: 0,
             dump: false,
+            address_filter: None,
+            priority_range: None,
         }
     }
 
@@ -57,7 +68,10 @@ impl PolicyGetRequest {
         PolicyGetRequest {
             handle,
             message,
+            netlink_flags: 0,
             dump: false,
+            address_filter: None,
+            priority_range: None,
         }
     }
 
@@ -67,7 +81,10 @@ impl PolicyGetRequest {
         PolicyGetRequest {
             handle,
             message,
+            netlink_flags: 0,
             dump: true,
+            address_filter: None,
+            priority_range: None,
         }
     }
 
@@ -109,6 +126,10 @@ impl PolicyGetRequest {
         self.message.nlas.push(XfrmAttrs::IfId(ifid));
         self
     }
+    /// Same as `ifid`, with a typed xfrm interface id.
+    pub fn interface_id(self, ifid: XfrmInterfaceId) -> Self {
+        self.ifid(ifid.0)
+    }
     pub fn mark(mut self, mark: u32, mask: u32) -> Self {
         self.message
             .nlas
@@ -116,48 +137,92 @@ impl PolicyGetRequest {
         self
     }
 
-    pub fn selector_protocol(mut self, proto: u8) -> Self {
-        self.message.user_policy_id.selector.proto = proto;
+    /// Replace the whole selector, e.g. with one built with `SelectorBuilder`.
+    pub fn selector(mut self, selector: impl Into<Selector>) -> Self {
+        self.message.user_policy_id.selector = selector.into();
         self
     }
-    pub fn selector_protocol_src_port(mut self, port: u16) -> Self {
-        self.message.user_policy_id.selector.sport = port;
-        self.message.user_policy_id.selector.sport_mask = u16::MAX;
-        self
+    pub fn selector_protocol(self, proto: u8) -> Self {
+        self.update_selector(|sel| sel.protocol(proto))
     }
-    pub fn selector_protocol_dst_port(mut self, port: u16) -> Self {
-        self.message.user_policy_id.selector.dport = port;
-        self.message.user_policy_id.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_src_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port(port))
+    }
+    pub fn selector_protocol_dst_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port(port))
+    }
+    // See SelectorBuilder::protocol_src_port_masked for which ranges can be matched.
+    pub fn selector_protocol_src_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port_masked(port, mask))
+    }
+    pub fn selector_protocol_dst_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port_masked(port, mask))
+    }
+    pub fn selector_protocol_type(self, proto_type: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_type(proto_type))
     }
-    pub fn selector_protocol_type(mut self, proto_type: u8) -> Self {
-        self.message.user_policy_id.selector.sport = proto_type as u16;
-        self.message.user_policy_id.selector.sport_mask = u16::MAX;
+    pub fn selector_protocol_code(self, proto_code: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_code(proto_code))
+    }
+    pub fn selector_protocol_gre_key(self, gre_key: u32) -> Self {
+        self.update_selector(|sel| sel.protocol_gre_key(gre_key))
+    }
+    pub fn selector_dev_id(self, id: u32) -> Self {
+        self.update_selector(|sel| sel.dev_id(id))
+    }
+    /// Same as `selector_dev_id`, looking up the index of the named interface.
+    /// Fails with `Error::InterfaceNotFound` if there is no such interface, rather
+    /// than leaving an ifindex of 0, which would match any interface.
+    pub fn selector_dev(self, name: &str) -> Result<Self, Error> {
+        Ok(self.selector_dev_id(ifindex_by_name(name)?))
+    }
+    fn update_selector<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(SelectorBuilder) -> SelectorBuilder,
+    {
+        self.message.user_policy_id.selector =
+            f(self.message.user_policy_id.selector.into()).build();
         self
     }
-    pub fn selector_protocol_code(mut self, proto_code: u8) -> Self {
-        self.message.user_policy_id.selector.dport = proto_code as u16;
-        self.message.user_policy_id.selector.dport_mask = u16::MAX;
+
+    /// Only return dumped policies whose selector source and destination addresses
+    /// fall within the given prefixes.
+    ///
+    /// Unlike the state dump, the kernel does not honor XFRMA_ADDRESS_FILTER for
+    /// policy dumps, so this filter is applied client-side as the policies are received.
+    pub fn address_filter(
+        mut self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> Self {
+        self.address_filter = Some((src_addr, src_prefix_len, dst_addr, dst_prefix_len));
         self
     }
-    pub fn selector_protocol_gre_key(mut self, gre_key: u32) -> Self {
-        self.message.user_policy_id.selector.sport = (gre_key >> 16) as u16;
-        self.message.user_policy_id.selector.sport_mask = u16::MAX;
-        self.message.user_policy_id.selector.dport = (gre_key & 0xffff) as u16;
-        self.message.user_policy_id.selector.dport_mask = u16::MAX;
+
+    /// Only return dumped policies with a priority between `min` and `max` (inclusive).
+    ///
+    /// The kernel can't filter policies on priority, so like `address_filter` this is
+    /// applied client-side as the policies are received.
+    pub fn priority_range(mut self, min: u32, max: u32) -> Self {
+        self.priority_range = Some((min, max));
         self
     }
-    pub fn selector_dev_id(mut self, id: u32) -> Self {
-        self.message.user_policy_id.selector.ifindex = id as i32;
+
+    /// Extra netlink header flags for the get, or the dump (ORed with NLM_F_DUMP), see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
         self
     }
 
-    /// Execute the request
-    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicyGetRequest {
-            mut handle,
             message,
             dump,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::GetPolicy(message));
@@ -167,16 +232,81 @@ impl PolicyGetRequest {
         } else {
             req.header.flags = NLM_F_REQUEST;
         }
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+        self.execute_with_progress(|_| ())
+    }
+
+    /// Execute the request, calling `on_progress` with the number of messages
+    /// received so far each time a message is received. Policies dropped by the
+    /// client-side filters are counted as well.
+    ///
+    /// Netlink doesn't report how many entries a dump will return. To show progress
+    /// against a total, fetch the policy counts with `get_spdinfo()` before starting the dump.
+    pub fn execute_with_progress<F>(
+        self,
+        mut on_progress: F,
+    ) -> impl TryStream<Ok = ModifyMessage, Error = Error>
+    where
+        F: FnMut(usize),
+    {
+        let mut handle = self.handle.clone();
+        let selector_check = check_selector(&self.message.user_policy_id.selector);
+        let dump = self.dump;
+        let address_filter = self.address_filter;
+        let priority_range = self.priority_range;
+        let req = self.into_message();
+        let mut received = 0;
 
         // A successful policy Get request returns with an Add/ModifyMessage response.
-        match handle.request(req) {
-            Ok(response) => {
-                Either::Left(response.map(move |msg| Ok(try_xfrmnl!(msg, XfrmMessage::AddPolicy))))
-            }
+        // If the SPD changed during a dump, the kernel flags the responses as interrupted.
+        match selector_check.and_then(|_| handle.request(req)) {
+            Ok(response) => Either::Left(
+                response
+                    .map(move |msg| {
+                        received += 1;
+                        on_progress(received);
+
+                        if dump && msg.header.flags & NLM_F_DUMP_INTR == NLM_F_DUMP_INTR {
+                            return Err(Error::DumpInterrupted);
+                        }
+                        Ok(try_xfrmnl!(msg, XfrmMessage::AddPolicy))
+                    })
+                    .try_filter(move |policy| {
+                        let info = &policy.user_policy_info;
+                        let addr_ok = match address_filter {
+                            Some((src_addr, src_prefix_len, dst_addr, dst_prefix_len)) => {
+                                selector_match(
+                                    &info.selector,
+                                    &src_addr,
+                                    src_prefix_len,
+                                    &dst_addr,
+                                    dst_prefix_len,
+                                )
+                            }
+                            None => true,
+                        };
+                        let prio_ok = match priority_range {
+                            Some((min, max)) => (min..=max).contains(&info.priority),
+                            None => true,
+                        };
+                        future::ready(addr_ok && prio_ok)
+                    }),
+            ),
             Err(e) => Either::Right(future::err::<ModifyMessage, Error>(e).into_stream()),
         }
     }
 
+    /// Execute the request and collect all the policies (after the client-side
+    /// filters), or return the first error.
+    pub async fn list(self) -> Result<Vec<ModifyMessage>, Error> {
+        self.execute().try_collect().await
+    }
+
     /// Return a mutable reference to the request message.
     pub fn message_mut(&mut self) -> &mut DelGetMessage {
         &mut self.message
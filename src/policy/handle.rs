@@ -1,13 +1,26 @@
 // SPDX-License-Identifier: MIT
 
+use futures::stream::TryStreamExt;
 use std::net::IpAddr;
 
 use super::{
     PolicyDeleteRequest, PolicyFlushRequest, PolicyGetDefaultRequest, PolicyGetRequest,
     PolicyGetSpdInfoRequest, PolicyModifyRequest, PolicySetDefaultRequest, PolicySetSpdInfoRequest,
 };
-use crate::Handle;
+use crate::{
+    address::{address_to_ip, check_prefixes},
+    errors::ENOENT,
+    Error, Handle, PolicyDirection,
+};
+use netlink_packet_xfrm::{policy::ModifyMessage, UserPolicyInfo, UserTemplate};
 
+/// A handle for xfrm policy requests, created with `Handle::policy()`.
+///
+/// Mirrors `StateHandle`: `add`, `update`, `delete`, `get`, `get_dump`, `flush`, and the
+/// statistics request `get_spdinfo`/`set_spdinfo`. Policies can also be addressed by
+/// index (`delete_index`, `get_index`), and the per-direction default action is
+/// available with `get_default_action`/`set_default_action`. The `_in`, `_out` and
+/// `_fwd` variants of `add`, `delete` and `get` set the policy direction.
 #[non_exhaustive]
 pub struct PolicyHandle(Handle);
 
@@ -34,6 +47,111 @@ impl PolicyHandle {
         )
     }
 
+    /// Same as `add`, with the direction set to XFRM_POLICY_FWD.
+    /// On a gateway, traffic tunneled to the networks behind it needs a fwd policy
+    /// along with the in policy.
+    pub fn add_fwd(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyModifyRequest {
+        self.add(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::Fwd.into())
+    }
+
+    /// Same as `add`, with the direction set to XFRM_POLICY_IN.
+    pub fn add_in(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyModifyRequest {
+        self.add(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::In.into())
+    }
+
+    /// Same as `add`, with the direction set to XFRM_POLICY_OUT.
+    pub fn add_out(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyModifyRequest {
+        self.add(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::Out.into())
+    }
+
+    /// Add xfrm policy for an existing SA bundle, deriving the selector from the templates.
+    ///
+    /// The selector matches the host addresses of the last (outermost) template, which is
+    /// what a transport mode bundle such as comp+esp between two hosts needs. There are no
+    /// separate source and destination addresses to pass, as they could only repeat those
+    /// of the outermost template, or contradict them and give a policy that never matches
+    /// its own bundle. Tunnel mode policies usually select inner traffic between subnets
+    /// instead, in which case use `add` with `add_template`.
+    pub fn add_with_templates(
+        &self,
+        direction: u8,
+        templates: Vec<UserTemplate>,
+    ) -> Result<PolicyModifyRequest, Error> {
+        let outer = templates.last().ok_or_else(|| Error::InvalidSelector {
+            reason: "no templates to derive the selector from".to_string(),
+        })?;
+
+        let addresses = address_to_ip(&outer.saddr, outer.family)
+            .zip(address_to_ip(&outer.id.daddr, outer.family));
+        let (src_addr, dst_addr) = addresses.ok_or_else(|| Error::InvalidSelector {
+            reason: format!("unsupported template address family {}", outer.family),
+        })?;
+        let prefix_len = if src_addr.is_ipv4() { 32 } else { 128 };
+
+        let mut req = self
+            .add(src_addr, prefix_len, dst_addr, prefix_len)
+            .direction(direction);
+        for template in templates {
+            req = req.add_template(template);
+        }
+        Ok(req)
+    }
+
+    /// Append templates to the template list of an existing policy, identified by its index.
+    ///
+    /// The kernel replaces the whole list on update, so the policy is fetched with
+    /// `get_index` and updated with its current templates followed by `templates`.
+    /// The same restrictions as `modify_in_place` apply, and the policy could be
+    /// changed by someone else between the get and the update.
+    ///
+    /// Fails with `Error::NotFound` if there is no policy with this index.
+    pub async fn append_templates(
+        &self,
+        index: u32,
+        templates: Vec<UserTemplate>,
+    ) -> Result<(), Error> {
+        let policy = self.fetch_index(index).await?;
+
+        let mut req = PolicyModifyRequest::from_policy(self.0.clone(), policy);
+        for template in templates {
+            req = req.add_template(template);
+        }
+        req.execute().await
+    }
+
+    // Get the policy with this index, the kernel answers ENOENT if there is none.
+    async fn fetch_index(&self, index: u32) -> Result<ModifyMessage, Error> {
+        let mut policies = self.get_index(index).direction((index & 7) as u8).execute();
+
+        match policies.try_next().await {
+            Ok(Some(policy)) => Ok(policy),
+            Ok(None) => Err(Error::NotFound),
+            Err(e) if e.errno() == Some(ENOENT) => Err(Error::NotFound),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Delete xfrm policy specifying selector parameters (equivalent to `ip xfrm policy delete <selector>`)
     pub fn delete(
         &self,
@@ -51,11 +169,88 @@ impl PolicyHandle {
         )
     }
 
+    /// Same as `delete`, with the direction set to XFRM_POLICY_FWD.
+    pub fn delete_fwd(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyDeleteRequest {
+        self.delete(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::Fwd.into())
+    }
+
+    /// Same as `delete`, with the direction set to XFRM_POLICY_IN.
+    pub fn delete_in(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyDeleteRequest {
+        self.delete(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::In.into())
+    }
+
     /// Delete xfrm policy specifying the index (equivalent to `ip xfrm policy delete index`)
+    ///
+    /// The kernel still looks the policy up in a direction, and only finds it if
+    /// `(index & 7) == direction`. The direction defaults to in (0), so for other
+    /// policies `direction()` must be called on the request, or use `delete_index_dir`.
     pub fn delete_index(&self, index: u32) -> PolicyDeleteRequest {
         PolicyDeleteRequest::new_index(self.0.clone(), index)
     }
 
+    /// Same as `delete_index`, with the direction set, e.g. for a kernel-assigned index
+    /// that was stored along with the direction of its policy.
+    /// Fails with `Error::IndexDirectionMismatch` if the index can't belong to that direction.
+    pub fn delete_index_dir(
+        &self,
+        index: u32,
+        direction: u8,
+    ) -> Result<PolicyDeleteRequest, Error> {
+        if index & 7 != u32::from(direction) {
+            return Err(Error::IndexDirectionMismatch(index, direction));
+        }
+        Ok(self.delete_index(index).direction(direction))
+    }
+
+    /// Same as `delete`, with the direction set to XFRM_POLICY_OUT.
+    pub fn delete_out(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyDeleteRequest {
+        self.delete(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::Out.into())
+    }
+
+    /// Check whether a policy exists for a selector and direction, e.g. for idempotent
+    /// provisioning. Policies with a mark, if_id or security context can't be found
+    /// this way, build the `get` request by hand for those.
+    pub async fn exists(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+        direction: u8,
+    ) -> Result<bool, Error> {
+        let mut policies = self
+            .get(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(direction)
+            .execute();
+
+        match policies.try_next().await {
+            Ok(policy) => Ok(policy.is_some()),
+            Err(e) if e.errno() == Some(ENOENT) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Flush xfrm policies (equivalent to `ip xfrm policy flush`)
     pub fn flush(&self) -> PolicyFlushRequest {
         PolicyFlushRequest::new(self.0.clone())
@@ -88,16 +283,80 @@ impl PolicyHandle {
         PolicyGetRequest::new_dump(self.0.clone())
     }
 
+    /// Same as `get`, with the direction set to XFRM_POLICY_FWD.
+    pub fn get_fwd(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyGetRequest {
+        self.get(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::Fwd.into())
+    }
+
+    /// Same as `get`, with the direction set to XFRM_POLICY_IN.
+    pub fn get_in(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyGetRequest {
+        self.get(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::In.into())
+    }
+
     /// Get xfrm policy specifying the index (equivalent to `ip xfrm policy get index`)
     pub fn get_index(&self, index: u32) -> PolicyGetRequest {
         PolicyGetRequest::new_index(self.0.clone(), index)
     }
 
+    /// Same as `get`, with the direction set to XFRM_POLICY_OUT.
+    pub fn get_out(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> PolicyGetRequest {
+        self.get(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+            .direction(PolicyDirection::Out.into())
+    }
+
     /// Get xfrm spd statistics (equivalent to `ip xfrm policy count`)
     pub fn get_spdinfo(&self) -> PolicyGetSpdInfoRequest {
         PolicyGetSpdInfoRequest::new(self.0.clone())
     }
 
+    /// Change an existing policy, identified by its index, without having to rebuild it.
+    ///
+    /// The policy is fetched with `get_index`, `f` is applied to its `UserPolicyInfo`,
+    /// and the result is sent back with an update. The other attributes of the policy
+    /// (templates, mark, if_id, security context) are kept as they are.
+    ///
+    /// The kernel matches the update on the selector, direction, mark and if_id, so
+    /// changing the selector or direction in `f` installs a new policy instead of
+    /// modifying this one. The index is restored after `f` runs, and the current
+    /// lifetime counters are not sent back since the kernel maintains them.
+    /// The policy direction is derived from the index (see `PolicyModifyRequest::index`),
+    /// policies with a mark or if_id must be fetched and updated by hand.
+    ///
+    /// Fails with `Error::NotFound` if there is no policy with this index.
+    pub async fn modify_in_place<F>(&self, index: u32, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut UserPolicyInfo),
+    {
+        let mut policy = self.fetch_index(index).await?;
+
+        f(&mut policy.user_policy_info);
+        policy.user_policy_info.index = index;
+
+        PolicyModifyRequest::from_policy(self.0.clone(), policy)
+            .execute()
+            .await
+    }
+
     /// Set the default xfrm action for input, output, forward policies (equivalent to `ip xfrm policy setdefault`)
     pub fn set_default_action(
         &self,
@@ -113,6 +372,45 @@ impl PolicyHandle {
         PolicySetSpdInfoRequest::new(self.0.clone())
     }
 
+    /// Same as `add`, but the addresses and prefix lengths are validated immediately
+    /// instead of when the request is executed.
+    pub fn try_add(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> Result<PolicyModifyRequest, Error> {
+        check_prefixes(&src_addr, src_prefix_len, &dst_addr, dst_prefix_len)?;
+        Ok(self.add(src_addr, src_prefix_len, dst_addr, dst_prefix_len))
+    }
+
+    /// Same as `get`, but the addresses and prefix lengths are validated immediately
+    /// instead of when the request is executed.
+    pub fn try_get(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> Result<PolicyGetRequest, Error> {
+        check_prefixes(&src_addr, src_prefix_len, &dst_addr, dst_prefix_len)?;
+        Ok(self.get(src_addr, src_prefix_len, dst_addr, dst_prefix_len))
+    }
+
+    /// Same as `update`, but the addresses and prefix lengths are validated immediately
+    /// instead of when the request is executed.
+    pub fn try_update(
+        &self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> Result<PolicyModifyRequest, Error> {
+        check_prefixes(&src_addr, src_prefix_len, &dst_addr, dst_prefix_len)?;
+        Ok(self.update(src_addr, src_prefix_len, dst_addr, dst_prefix_len))
+    }
+
     /// Update xfrm policy (equivalent to `ip xfrm policy update`)
     pub fn update(
         &self,
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT
+
+use crate::{XfrmInterfaceId, XfrmMode};
+use netlink_packet_xfrm::{policy::ModifyMessage, Mark, UserTemplate, XfrmAttrs};
+
+/// Accessors for the attributes of policies returned by get and dump requests.
+pub trait PolicyMessageExt {
+    /// The templates of the policy, empty if it has none (e.g. for an allow or block
+    /// policy without IPsec). The mode of each one is `XfrmMode::from(template.mode)`.
+    fn templates(&self) -> &[UserTemplate];
+
+    /// Whether any of the policy templates is in tunnel mode.
+    fn is_tunnel(&self) -> bool {
+        self.templates()
+            .iter()
+            .any(|template| XfrmMode::from(template.mode) == XfrmMode::Tunnel)
+    }
+
+    /// The xfrm interface the policy is bound to, if any.
+    fn interface_id(&self) -> Option<XfrmInterfaceId>;
+
+    /// The mark (XFRMA_MARK) of the policy, if it has one.
+    fn mark(&self) -> Option<Mark>;
+}
+
+impl PolicyMessageExt for ModifyMessage {
+    fn templates(&self) -> &[UserTemplate] {
+        self.nlas
+            .iter()
+            .find_map(|nla| match nla {
+                XfrmAttrs::Template(templates) => Some(templates.as_slice()),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    fn interface_id(&self) -> Option<XfrmInterfaceId> {
+        self.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::IfId(ifid) => Some(XfrmInterfaceId(*ifid)),
+            _ => None,
+        })
+    }
+
+    fn mark(&self) -> Option<Mark> {
+        self.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::Mark(mark) => Some(Mark {
+                value: mark.value,
+                mask: mark.mask,
+            }),
+            _ => None,
+        })
+    }
+}
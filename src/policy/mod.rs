@@ -15,8 +15,25 @@ pub use self::get::*;
 mod handle;
 pub use self::handle::*;
 
+mod message;
+pub use self::message::*;
+
 mod modify;
 pub use self::modify::*;
 
+#[cfg(feature = "ipnet")]
+mod net;
+
+#[cfg(feature = "serde")]
+mod spec;
+#[cfg(feature = "serde")]
+pub use self::spec::*;
+
 mod spdinfo;
 pub use self::spdinfo::*;
+
+mod template;
+pub use self::template::*;
+
+#[cfg(test)]
+mod test;
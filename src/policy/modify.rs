@@ -3,19 +3,30 @@
 use futures::stream::StreamExt;
 use std::net::IpAddr;
 
-use crate::{try_nl, Error, Handle};
-use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
+use crate::{
+    address::check_selector, link::ifindex_by_name, try_nl, Error, Handle, LifetimeConfig,
+    SelectorBuilder, XfrmInterfaceId,
+};
+use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_EXCL, NLM_F_REQUEST};
 use netlink_packet_xfrm::{
-    policy::ModifyMessage, Mark, SecurityCtx, UserPolicyType, UserTemplate, XfrmAttrs, XfrmMessage,
+    policy::ModifyMessage, Mark, SecurityCtx, Selector, UserPolicyType, UserTemplate, XfrmAttrs,
+    XfrmMessage, XFRM_POLICY_OUT,
 };
 
+/// The priority of a policy with an empty selector when set by
+/// `PolicyModifyRequest::default_priority`, more specific selectors get lower values.
+pub const DEFAULT_PRIORITY_BASE: u32 = 0x40000;
+
 /// A request to add or update xfrm policies. This is equivalent to the `ip xfrm policy add|update` commands.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicyModifyRequest {
     handle: Handle,
     message: ModifyMessage,
+    netlink_flags: u16,
     update: bool,
     templates: Vec<UserTemplate>,
+    direction_set: bool,
 }
 
 impl PolicyModifyRequest {
@@ -41,13 +52,47 @@ impl PolicyModifyRequest {
         PolicyModifyRequest {
             handle,
             message,
+            netlink_flags: 0,
             update,
             templates: Vec::default(),
+            direction_set: false,
+        }
+    }
+
+    // Used to update an existing policy returned by the kernel, which already
+    // carries its direction, selector and attributes (templates, mark, ...).
+    pub(crate) fn from_policy(handle: Handle, mut message: ModifyMessage) -> Self {
+        // The current lifetime is maintained by the kernel, don't send back stale counters.
+        message.user_policy_info.lifetime_cur = Default::default();
+
+        // Move the existing templates out of the message, so that add_template appends
+        // to them and replace_templates replaces them.
+        let mut templates = Vec::default();
+        message.nlas.retain(|nla| match nla {
+            XfrmAttrs::Template(tmpls) => {
+                templates.extend_from_slice(tmpls);
+                false
+            }
+            _ => true,
+        });
+
+        PolicyModifyRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+            update: true,
+            templates,
+            direction_set: true,
         }
     }
 
+    // Required: the direction defaults to XFRM_POLICY_IN (0) in the message, which
+    // would silently install the policy in the wrong direction if it was forgotten.
+    // Execute fails with Error::MissingDirection unless this is called, even when
+    // the direction was set through message_mut().
     pub fn direction(mut self, direction: u8) -> Self {
         self.message.user_policy_info.direction = direction;
+        self.direction_set = true;
         self
     }
 
@@ -84,14 +129,95 @@ impl PolicyModifyRequest {
         self.message.user_policy_info.index = index;
         self
     }
+    /// Set the index to the smallest valid index for the direction that is not below
+    /// `base`, following the `(index & 7) == dir` rule described on `index`. An index of
+    /// 0 lets the kernel choose, so for XFRM_POLICY_IN the smallest index is 8.
+    /// The direction must be set first.
+    pub fn index_auto(mut self, base: u32) -> Result<Self, Error> {
+        if !self.direction_set {
+            return Err(Error::MissingDirection);
+        }
+        let dir = u32::from(self.message.user_policy_info.direction);
+
+        let mut index = (base & !7) | dir;
+        if index < base || index == 0 {
+            index = index.checked_add(8).ok_or(Error::InvalidIndex(base))?;
+        }
+
+        self.message.user_policy_info.index = index;
+        Ok(self)
+    }
+    /// Advanced: set the `share` field of the policy (XFRM_SHARE_*, or a `ShareMode`).
+    ///
+    /// This mirrors the policy info the kernel keeps for every policy, and is mostly
+    /// useful to replicate a policy exactly. The kernel stores it but doesn't act on it,
+    /// the sharing of the SAs is governed by the `share` of each template instead.
+    ///
+    /// Per-socket policies (set with the IP_XFRM_POLICY/IPV6_XFRM_POLICY socket options)
+    /// can't be expressed with this request: their direction is XFRM_POLICY_MAX plus the
+    /// direction and their index is chosen by the kernel, and netlink only accepts the
+    /// in, out and fwd directions. They are not returned by policy dumps either.
+    pub fn share(mut self, share: impl Into<u8>) -> Self {
+        self.message.user_policy_info.share = share.into();
+        self
+    }
+    // Lower values take precedence. The default of 0 (also used by iproute2) makes the
+    // policy take precedence over every policy with an explicit priority, so a broad
+    // catch-all policy installed without one shadows the more specific policies.
     pub fn priority(mut self, priority: u32) -> Self {
         self.message.user_policy_info.priority = priority;
         self
     }
+    /// Set the priority from how specific the selector is, so that policies with
+    /// narrower selectors take precedence over broader ones, lowering the priority value
+    /// from a base of `DEFAULT_PRIORITY_BASE`.
+    ///
+    /// The priority depends on the direction, which must be set first. As with routes,
+    /// the prefix of the peer comes first: the destination prefix for out policies, and
+    /// the source prefix for in and fwd policies. The local prefix, then a protocol,
+    /// ports or an interface in the selector only break ties between equal peer prefixes.
+    /// Call this after the selector setters.
+    pub fn default_priority(mut self) -> Result<Self, Error> {
+        if !self.direction_set {
+            return Err(Error::MissingDirection);
+        }
+        let info = &self.message.user_policy_info;
+        let sel = &info.selector;
+
+        let (peer_prefix_len, local_prefix_len) = if info.direction == XFRM_POLICY_OUT {
+            (sel.prefixlen_d, sel.prefixlen_s)
+        } else {
+            (sel.prefixlen_s, sel.prefixlen_d)
+        };
+
+        // A peer prefix bit outweighs everything else, a local prefix bit outweighs the
+        // protocol, ports and interface together.
+        let mut specificity =
+            (u32::from(peer_prefix_len) << 10) + (u32::from(local_prefix_len) << 2);
+        if sel.proto != 0 {
+            specificity += 1;
+        }
+        if sel.sport_mask != 0 {
+            specificity += 1;
+        }
+        if sel.dport_mask != 0 {
+            specificity += 1;
+        }
+        if sel.ifindex != 0 {
+            specificity += 1;
+        }
+
+        self.message.user_policy_info.priority = DEFAULT_PRIORITY_BASE - specificity;
+        Ok(self)
+    }
     pub fn ifid(mut self, ifid: u32) -> Self {
         self.message.nlas.push(XfrmAttrs::IfId(ifid));
         self
     }
+    /// Same as `ifid`, with a typed xfrm interface id.
+    pub fn interface_id(self, ifid: XfrmInterfaceId) -> Self {
+        self.ifid(ifid.0)
+    }
     pub fn flags(mut self, flags: u8) -> Self {
         self.message.user_policy_info.flags = flags;
         self
@@ -134,40 +260,78 @@ impl PolicyModifyRequest {
         self.message.user_policy_info.lifetime_cfg.hard_packet_limit = hard;
         self
     }
-
-    pub fn selector_protocol(mut self, proto: u8) -> Self {
-        self.message.user_policy_info.selector.proto = proto;
+    /// Set all the limits at once, replacing the ones set by `time_limit`,
+    /// `time_use_limit`, `byte_limit` and `packet_limit`.
+    pub fn lifetimes(mut self, lifetimes: LifetimeConfig) -> Self {
+        lifetimes.apply(&mut self.message.user_policy_info.lifetime_cfg);
         self
     }
-    pub fn selector_protocol_src_port(mut self, port: u16) -> Self {
-        self.message.user_policy_info.selector.sport = port;
-        self.message.user_policy_info.selector.sport_mask = u16::MAX;
+
+    /// Replace the whole selector, e.g. with one built with `SelectorBuilder`.
+    pub fn selector(mut self, selector: impl Into<Selector>) -> Self {
+        self.message.user_policy_info.selector = selector.into();
         self
     }
-    pub fn selector_protocol_dst_port(mut self, port: u16) -> Self {
-        self.message.user_policy_info.selector.dport = port;
-        self.message.user_policy_info.selector.dport_mask = u16::MAX;
-        self
+    // Replaces the selector (inner) addresses given when the request was created.
+    // These are independent from the template (outer) addresses.
+    // The selector family is taken from these addresses, so both must be of the same
+    // family. See SelectorBuilder::addresses for what IPv6 selectors can match on.
+    pub fn selector_addresses(
+        self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> Self {
+        self.update_selector(|sel| {
+            sel.addresses(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+        })
     }
-    pub fn selector_protocol_type(mut self, proto_type: u8) -> Self {
-        self.message.user_policy_info.selector.sport = proto_type as u16;
-        self.message.user_policy_info.selector.sport_mask = u16::MAX;
-        self
+    pub fn selector_protocol(self, proto: u8) -> Self {
+        self.update_selector(|sel| sel.protocol(proto))
     }
-    pub fn selector_protocol_code(mut self, proto_code: u8) -> Self {
-        self.message.user_policy_info.selector.dport = proto_code as u16;
-        self.message.user_policy_info.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_src_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port(port))
     }
-    pub fn selector_protocol_gre_key(mut self, gre_key: u32) -> Self {
-        self.message.user_policy_info.selector.sport = (gre_key >> 16) as u16;
-        self.message.user_policy_info.selector.sport_mask = u16::MAX;
-        self.message.user_policy_info.selector.dport = (gre_key & 0xffff) as u16;
-        self.message.user_policy_info.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_dst_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port(port))
     }
-    pub fn selector_dev_id(mut self, id: u32) -> Self {
-        self.message.user_policy_info.selector.ifindex = id as i32;
+    // See SelectorBuilder::protocol_src_port_masked for which ranges can be matched.
+    pub fn selector_protocol_src_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port_masked(port, mask))
+    }
+    pub fn selector_protocol_dst_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port_masked(port, mask))
+    }
+    pub fn selector_protocol_type(self, proto_type: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_type(proto_type))
+    }
+    pub fn selector_protocol_code(self, proto_code: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_code(proto_code))
+    }
+    pub fn selector_protocol_gre_key(self, gre_key: u32) -> Self {
+        self.update_selector(|sel| sel.protocol_gre_key(gre_key))
+    }
+    // Clear the protocol, ports (or type/code, GRE key) and their masks back to matching
+    // any traffic, e.g. when a request cloned from a template needs a broader selector.
+    pub fn selector_any_protocol(self) -> Self {
+        self.update_selector(|sel| sel.any_protocol())
+    }
+    pub fn selector_dev_id(self, id: u32) -> Self {
+        self.update_selector(|sel| sel.dev_id(id))
+    }
+    /// Same as `selector_dev_id`, looking up the index of the named interface.
+    /// Fails with `Error::InterfaceNotFound` if there is no such interface, rather
+    /// than leaving an ifindex of 0, which would match any interface.
+    pub fn selector_dev(self, name: &str) -> Result<Self, Error> {
+        Ok(self.selector_dev_id(ifindex_by_name(name)?))
+    }
+    fn update_selector<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(SelectorBuilder) -> SelectorBuilder,
+    {
+        self.message.user_policy_info.selector =
+            f(self.message.user_policy_info.selector.into()).build();
         self
     }
 
@@ -175,18 +339,47 @@ impl PolicyModifyRequest {
     // directly. When execute is called, all of the added templates
     // are grouped into one array and passed to the kernel as a
     // single XFRMA_TMPL attribute.
-    pub fn add_template(mut self, template: UserTemplate) -> Self {
-        self.templates.push(template);
+    // On update, the kernel replaces the whole template list of the policy with
+    // this one: templates not added again are removed from the policy. To append
+    // to the templates of an existing policy, use PolicyHandle::append_templates.
+    pub fn add_template(mut self, template: impl Into<UserTemplate>) -> Self {
+        self.templates.push(template.into());
+        self
+    }
+    /// Replace the templates added so far with `templates`, which become the
+    /// complete template list of the policy, as sent to the kernel.
+    pub fn replace_templates(mut self, templates: Vec<UserTemplate>) -> Self {
+        self.templates = templates;
         self
     }
 
-    /// Execute the request.
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Fail with EEXIST if the policy already exists, instead of replacing it, e.g. to
+    /// detect configuration drift.
+    ///
+    /// The kernel doesn't look at the NLM_F_EXCL/NLM_F_REPLACE flags for policies: an
+    /// add (XFRM_MSG_NEWPOLICY) is always exclusive, and an update (XFRM_MSG_UPDPOLICY)
+    /// always replaces. So this turns an update into an add, and sets NLM_F_EXCL to
+    /// make the intent explicit.
+    pub fn exclusive(mut self) -> Self {
+        self.update = false;
+        self.netlink_flags |= NLM_F_EXCL;
+        self
+    }
+
+    /// Extra netlink header flags for the add or update, e.g. NLM_F_REPLACE, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicyModifyRequest {
-            mut handle,
             mut message,
+            netlink_flags,
             update,
             templates,
+            ..
         } = self;
 
         if !templates.is_empty() {
@@ -199,8 +392,16 @@ impl PolicyModifyRequest {
             NetlinkMessage::from(XfrmMessage::AddPolicy(message))
         };
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        self.check()?;
 
-        let mut response = handle.request(req)?;
+        let mut handle = self.handle.clone();
+        let mut response = handle.request(self.into_message())?;
 
         while let Some(message) = response.next().await {
             try_nl!(message);
@@ -209,30 +410,36 @@ impl PolicyModifyRequest {
     }
 
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let PolicyModifyRequest {
-            mut handle,
-            mut message,
-            update,
-            templates,
-        } = self;
+        self.check()?;
 
-        if !templates.is_empty() {
-            message.nlas.push(XfrmAttrs::Template(templates));
-        }
-
-        let mut req = if update {
-            NetlinkMessage::from(XfrmMessage::UpdatePolicy(message))
-        } else {
-            NetlinkMessage::from(XfrmMessage::AddPolicy(message))
-        };
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
         Ok(())
     }
 
+    fn check(&self) -> Result<(), Error> {
+        if !self.direction_set {
+            return Err(Error::MissingDirection);
+        }
+        check_selector(&self.message.user_policy_info.selector)?;
+
+        #[cfg(feature = "tracing")]
+        if self.message.user_policy_info.priority == 0 {
+            tracing::warn!(
+                direction = self.message.user_policy_info.direction,
+                "installing an xfrm policy with priority 0, which takes precedence over \
+                 every policy with an explicit priority"
+            );
+        }
+        Ok(())
+    }
+
     /// Return a mutable reference to the request message.
     pub fn message_mut(&mut self) -> &mut ModifyMessage {
         &mut self.message
@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: MIT
+
+use ::ipnet::IpNet;
+
+use super::{PolicyDeleteRequest, PolicyGetRequest, PolicyHandle, PolicyModifyRequest};
+
+/// Constructors taking the selector networks as `ipnet::IpNet`, for callers already
+/// using the `ipnet` crate. Requires the `ipnet` feature.
+///
+/// These are the same as the `(IpAddr, prefix_len)` constructors, which remain the
+/// canonical form, with the address and prefix length of each network.
+impl PolicyHandle {
+    /// Same as `add`, with the selector networks given as `IpNet`.
+    pub fn add_net(&self, src: IpNet, dst: IpNet) -> PolicyModifyRequest {
+        self.add(src.addr(), src.prefix_len(), dst.addr(), dst.prefix_len())
+    }
+
+    /// Same as `delete`, with the selector networks given as `IpNet`.
+    pub fn delete_net(&self, src: IpNet, dst: IpNet) -> PolicyDeleteRequest {
+        self.delete(src.addr(), src.prefix_len(), dst.addr(), dst.prefix_len())
+    }
+
+    /// Same as `get`, with the selector networks given as `IpNet`.
+    pub fn get_net(&self, src: IpNet, dst: IpNet) -> PolicyGetRequest {
+        self.get(src.addr(), src.prefix_len(), dst.addr(), dst.prefix_len())
+    }
+
+    /// Same as `update`, with the selector networks given as `IpNet`.
+    pub fn update_net(&self, src: IpNet, dst: IpNet) -> PolicyModifyRequest {
+        self.update(src.addr(), src.prefix_len(), dst.addr(), dst.prefix_len())
+    }
+}
@@ -10,29 +10,49 @@ use netlink_packet_xfrm::{
 };
 
 /// A request to get xfrm policy statistics. This is equivalent to the `ip xfrm policy count` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicyGetSpdInfoRequest {
     handle: Handle,
     message: GetSpdInfoMessage,
+    netlink_flags: u16,
 }
 
 impl PolicyGetSpdInfoRequest {
     pub(crate) fn new(handle: Handle) -> Self {
         let message = GetSpdInfoMessage { flags: u32::MAX };
 
-        PolicyGetSpdInfoRequest { handle, message }
+        PolicyGetSpdInfoRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
-    /// Execute the request
-    pub async fn execute(self) -> Result<NewSpdInfoMessage, Error> {
+    /// Extra netlink header flags for the SPD info get, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicyGetSpdInfoRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::GetSpdInfo(message));
-
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<NewSpdInfoMessage, Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -48,11 +68,78 @@ impl PolicyGetSpdInfoRequest {
     }
 }
 
+/// Accessors for the statistics returned by a `get_spdinfo` request.
+pub trait SpdInfoMessageExt {
+    /// The number of policies in the SPD, as (in, out, fwd) counts.
+    fn count(&self) -> Option<(u32, u32, u32)>;
+
+    /// The number of socket policies, as (in, out, fwd) counts.
+    fn socket_count(&self) -> Option<(u32, u32, u32)>;
+
+    /// The current number of buckets in the SPD hash tables.
+    fn hash_count(&self) -> Option<u32>;
+
+    /// The maximum number of buckets the SPD hash tables can grow to.
+    fn hash_max(&self) -> Option<u32>;
+
+    /// The IPv4 hash thresholds, as (lbits, rbits).
+    fn hthresh4(&self) -> Option<(u8, u8)>;
+
+    /// The IPv6 hash thresholds, as (lbits, rbits).
+    fn hthresh6(&self) -> Option<(u8, u8)>;
+}
+
+impl SpdInfoMessageExt for NewSpdInfoMessage {
+    fn count(&self) -> Option<(u32, u32, u32)> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SpdInfoAttrs::SpdInfo(info) => Some((info.incnt, info.outcnt, info.fwdcnt)),
+            _ => None,
+        })
+    }
+
+    fn socket_count(&self) -> Option<(u32, u32, u32)> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SpdInfoAttrs::SpdInfo(info) => Some((info.inscnt, info.outscnt, info.fwdscnt)),
+            _ => None,
+        })
+    }
+
+    fn hash_count(&self) -> Option<u32> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SpdInfoAttrs::SpdHInfo(hinfo) => Some(hinfo.spdhcnt),
+            _ => None,
+        })
+    }
+
+    fn hash_max(&self) -> Option<u32> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SpdInfoAttrs::SpdHInfo(hinfo) => Some(hinfo.spdhmcnt),
+            _ => None,
+        })
+    }
+
+    fn hthresh4(&self) -> Option<(u8, u8)> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SpdInfoAttrs::SpdIpv4HThresh(hthresh) => Some((hthresh.lbits, hthresh.rbits)),
+            _ => None,
+        })
+    }
+
+    fn hthresh6(&self) -> Option<(u8, u8)> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SpdInfoAttrs::SpdIpv6HThresh(hthresh) => Some((hthresh.lbits, hthresh.rbits)),
+            _ => None,
+        })
+    }
+}
+
 /// A request to set xfrm policy statistics. This is equivalent to the `ip xfrm policy set` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct PolicySetSpdInfoRequest {
     handle: Handle,
     message: NewSpdInfoMessage,
+    netlink_flags: u16,
 }
 
 impl PolicySetSpdInfoRequest {
@@ -62,7 +149,11 @@ impl PolicySetSpdInfoRequest {
             ..Default::default()
         };
 
-        PolicySetSpdInfoRequest { handle, message }
+        PolicySetSpdInfoRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     pub fn hthresh4(mut self, lbits: u8, rbits: u8) -> Self {
@@ -83,15 +174,62 @@ impl PolicySetSpdInfoRequest {
         self
     }
 
-    /// Execute the request
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Set both the IPv4 and IPv6 hash thresholds at once.
+    /// Unlike `hthresh4`/`hthresh6`, out of range values are rejected instead
+    /// of being ignored, and nothing is set unless all of them are valid.
+    pub fn hthresh(
+        mut self,
+        lbits4: u8,
+        rbits4: u8,
+        lbits6: u8,
+        rbits6: u8,
+    ) -> Result<Self, Error> {
+        if lbits4 > 32 || rbits4 > 32 {
+            return Err(Error::InvalidHThresh(lbits4, rbits4));
+        }
+        if lbits6 > 128 || rbits6 > 128 {
+            return Err(Error::InvalidHThresh(lbits6, rbits6));
+        }
+
+        self.message
+            .nlas
+            .push(SpdInfoAttrs::SpdIpv4HThresh(SpdHThresh {
+                lbits: lbits4,
+                rbits: rbits4,
+            }));
+        self.message
+            .nlas
+            .push(SpdInfoAttrs::SpdIpv6HThresh(SpdHThresh {
+                lbits: lbits6,
+                rbits: rbits6,
+            }));
+        Ok(self)
+    }
+
+    /// Extra netlink header flags for the SPD info update, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let PolicySetSpdInfoRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::NewSpdInfo(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: MIT
+
+use futures::stream::{self, StreamExt};
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{PolicyHandle, PolicyModifyRequest, UserTemplateBuilder};
+use crate::Error;
+use netlink_packet_xfrm::UserTemplate;
+
+// The number of add_many requests awaiting their ACK at the same time.
+const ADD_MANY_IN_FLIGHT: usize = 64;
+
+/// A serializable description of an xfrm policy, for config-driven provisioning.
+/// Requires the `serde` feature.
+///
+/// The upstream message structs can't derive serde, so this mirrors the fields set by
+/// `PolicyModifyRequest`. Addresses use the `std::net::IpAddr` serde representation:
+/// a string such as `"10.0.0.1"` or `"fd00::1"` in human readable formats (JSON, YAML),
+/// and a tagged byte array in binary formats.
+///
+/// Optional fields that are not set are left at the kernel/iproute2 defaults.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PolicySpec {
+    pub src_addr: IpAddr,
+    pub src_prefix_len: u8,
+    pub dst_addr: IpAddr,
+    pub dst_prefix_len: u8,
+    pub direction: u8,
+    #[serde(default)]
+    pub action: u8,
+    #[serde(default)]
+    pub priority: u32,
+    #[serde(default)]
+    pub index: Option<u32>,
+    #[serde(default)]
+    pub ptype: Option<u8>,
+    #[serde(default)]
+    pub ifid: Option<u32>,
+    #[serde(default)]
+    pub flags: u8,
+    #[serde(default)]
+    pub mark: Option<MarkSpec>,
+    #[serde(default)]
+    pub selector_protocol: Option<u8>,
+    #[serde(default)]
+    pub selector_src_port: Option<u16>,
+    #[serde(default)]
+    pub selector_dst_port: Option<u16>,
+    #[serde(default)]
+    pub time_limit: Option<LimitSpec>,
+    #[serde(default)]
+    pub time_use_limit: Option<LimitSpec>,
+    #[serde(default)]
+    pub byte_limit: Option<LimitSpec>,
+    #[serde(default)]
+    pub packet_limit: Option<LimitSpec>,
+    #[serde(default)]
+    pub templates: Vec<TemplateSpec>,
+}
+
+/// A mark value and mask, see `PolicyModifyRequest::mark`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MarkSpec {
+    pub value: u32,
+    pub mask: u32,
+}
+
+/// A pair of soft and hard lifetime limits, see `PolicyModifyRequest::time_limit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LimitSpec {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// A serializable description of a policy template, see `UserTemplateBuilder`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TemplateSpec {
+    pub src_addr: IpAddr,
+    pub dst_addr: IpAddr,
+    pub protocol: u8,
+    #[serde(default)]
+    pub spi: u32,
+    #[serde(default)]
+    pub mode: u8,
+    #[serde(default)]
+    pub reqid: u32,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub share: u8,
+}
+
+impl From<&TemplateSpec> for UserTemplate {
+    fn from(spec: &TemplateSpec) -> UserTemplate {
+        UserTemplateBuilder::new(spec.src_addr, spec.dst_addr)
+            .protocol(spec.protocol)
+            .spi(spec.spi)
+            .mode(spec.mode)
+            .reqid(spec.reqid)
+            .optional(spec.optional)
+            .share(spec.share)
+            .build()
+    }
+}
+
+impl PolicySpec {
+    /// Build the request adding (or updating, if `update` is true) the described policy.
+    pub fn to_request(&self, handle: &PolicyHandle, update: bool) -> PolicyModifyRequest {
+        let mut req = if update {
+            handle.update(
+                self.src_addr,
+                self.src_prefix_len,
+                self.dst_addr,
+                self.dst_prefix_len,
+            )
+        } else {
+            handle.add(
+                self.src_addr,
+                self.src_prefix_len,
+                self.dst_addr,
+                self.dst_prefix_len,
+            )
+        };
+
+        req = req
+            .direction(self.direction)
+            .action(self.action)
+            .priority(self.priority)
+            .flags(self.flags);
+
+        if let Some(index) = self.index {
+            req = req.index(index);
+        }
+        if let Some(ptype) = self.ptype {
+            req = req.ptype(ptype);
+        }
+        if let Some(ifid) = self.ifid {
+            req = req.ifid(ifid);
+        }
+        if let Some(mark) = self.mark {
+            req = req.mark(mark.value, mark.mask);
+        }
+        if let Some(proto) = self.selector_protocol {
+            req = req.selector_protocol(proto);
+        }
+        if let Some(port) = self.selector_src_port {
+            req = req.selector_protocol_src_port(port);
+        }
+        if let Some(port) = self.selector_dst_port {
+            req = req.selector_protocol_dst_port(port);
+        }
+        if let Some(limit) = self.time_limit {
+            req = req.time_limit(limit.soft, limit.hard);
+        }
+        if let Some(limit) = self.time_use_limit {
+            req = req.time_use_limit(limit.soft, limit.hard);
+        }
+        if let Some(limit) = self.byte_limit {
+            req = req.byte_limit(limit.soft, limit.hard);
+        }
+        if let Some(limit) = self.packet_limit {
+            req = req.packet_limit(limit.soft, limit.hard);
+        }
+        for template in &self.templates {
+            req = req.add_template(template);
+        }
+        req
+    }
+}
+
+impl PolicyHandle {
+    /// Add every described policy, e.g. a whole ruleset from a config file, and return
+    /// the result for each spec, in the same order. Requires the `serde` feature.
+    ///
+    /// The requests are pipelined: up to 64 of them are sent without waiting for the
+    /// previous ACKs. A failing policy doesn't stop the others, so the results tell
+    /// exactly which ones failed. Nothing is rolled back, the policies that were
+    /// added stay in place.
+    pub async fn add_many(&self, specs: Vec<PolicySpec>) -> Vec<Result<(), Error>> {
+        stream::iter(specs)
+            .map(|spec| spec.to_request(self, false).execute())
+            .buffered(ADD_MANY_IN_FLIGHT)
+            .collect()
+            .await
+    }
+}
@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+
+use netlink_packet_xfrm::UserTemplate;
+
+/// A builder for the templates attached to an xfrm policy. This is equivalent to the
+/// `tmpl` portion of the `ip xfrm policy add|update` commands.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct UserTemplateBuilder {
+    template: UserTemplate,
+}
+
+impl UserTemplateBuilder {
+    /// The addresses are the SA endpoints. For tunnel and BEET mode these are the outer
+    /// (gateway) addresses, independent of the inner addresses in the policy selector.
+    pub fn new(src_addr: IpAddr, dst_addr: IpAddr) -> Self {
+        let mut template = UserTemplate::default();
+
+        template.source(&src_addr);
+        template.destination(&dst_addr);
+
+        UserTemplateBuilder { template }
+    }
+
+    pub fn protocol(mut self, protocol: u8) -> Self {
+        self.template.protocol(protocol);
+        self
+    }
+    pub fn spi(mut self, spi: u32) -> Self {
+        self.template.spi(spi);
+        self
+    }
+    pub fn mode(mut self, mode: u8) -> Self {
+        self.template.mode(mode);
+        self
+    }
+    pub fn reqid(mut self, reqid: u32) -> Self {
+        self.template.reqid(reqid);
+        self
+    }
+    // Equivalent to iproute2 'level use' (true) or 'level required' (false)
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.template.optional(optional);
+        self
+    }
+
+    /// Set how SAs resolved from this template may be shared between flows.
+    /// Accepts a `ShareMode` or a raw XFRM_SHARE_* value. Use `ShareMode::Unique`
+    /// for per-session SAs that must never be reused.
+    pub fn share(mut self, share: impl Into<u8>) -> Self {
+        self.template.share = share.into();
+        self
+    }
+
+    // Bitmasks of the acceptable algorithms for the SA.
+    // iproute2 always sets these to all ones (u32::MAX).
+    pub fn auth_algos(mut self, aalgos: u32) -> Self {
+        self.template.aalgos = aalgos;
+        self
+    }
+    pub fn encryption_algos(mut self, ealgos: u32) -> Self {
+        self.template.ealgos = ealgos;
+        self
+    }
+    pub fn compression_algos(mut self, calgos: u32) -> Self {
+        self.template.calgos = calgos;
+        self
+    }
+
+    /// Build the template, ready to be passed to `add_template`.
+    pub fn build(self) -> UserTemplate {
+        self.template
+    }
+}
+
+impl From<UserTemplateBuilder> for UserTemplate {
+    fn from(builder: UserTemplateBuilder) -> UserTemplate {
+        builder.build()
+    }
+}
@@ -0,0 +1,411 @@
+// SPDX-License-Identifier: MIT
+
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::{
+    decode_message, encode_message, mock_handle, Error, MockController, PolicyMessageExt,
+    ShareMode, SpdInfoMessageExt, UserTemplateBuilder, XfrmMode,
+};
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
+use netlink_packet_xfrm::{
+    Address, Selector, XfrmMessage, AF_INET, AF_INET6, IPPROTO_COMP, IPPROTO_ESP, IPPROTO_TCP,
+    XFRM_MODE_TUNNEL, XFRM_POLICY_FWD, XFRM_POLICY_IN, XFRM_POLICY_OUT,
+};
+
+const SRC_NET: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0));
+const DST_NET: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0));
+
+const GW_SRC: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+const GW_DST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+// The xfrm_selector follows the netlink header in xfrm_userpolicy_info.
+const SELECTOR: usize = 16;
+
+const ENOENT: i32 = 2;
+
+fn selector(message: &NetlinkMessage<XfrmMessage>) -> Selector {
+    match &message.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddPolicy(msg)) => msg.user_policy_info.selector,
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn ipv6_policy_with_ports_round_trips() {
+    let (handle, _) = mock_handle();
+    let mut message = handle
+        .policy()
+        .add(SRC_NET, 64, DST_NET, 64)
+        .direction(XFRM_POLICY_OUT)
+        .selector_protocol(IPPROTO_TCP)
+        .selector_protocol_src_port(1234)
+        .selector_protocol_dst_port(443)
+        .selector_dev_id(3)
+        .into_message();
+    let buf = encode_message(&mut message);
+
+    // Addresses, then the ports and their masks in network byte order, the family,
+    // the prefix lengths, the protocol and the interface index.
+    let sel = &buf[SELECTOR..];
+    assert_eq!(
+        sel[..16],
+        Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0).octets()
+    );
+    assert_eq!(
+        sel[16..32],
+        Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0).octets()
+    );
+    assert_eq!(
+        sel[32..40],
+        [0x01, 0xbb, 0xff, 0xff, 0x04, 0xd2, 0xff, 0xff]
+    );
+    assert_eq!(u16::from_ne_bytes([sel[40], sel[41]]), 10);
+    assert_eq!(sel[42..45], [64, 64, IPPROTO_TCP]);
+    assert_eq!(i32::from_ne_bytes([sel[48], sel[49], sel[50], sel[51]]), 3);
+
+    let decoded = decode_message(&buf).unwrap();
+    assert_eq!(selector(&decoded), selector(&message));
+}
+
+#[tokio::test]
+async fn masked_port_range_survives_encoding() {
+    let (handle, _) = mock_handle();
+    // Source ports 1024-2047, any destination port.
+    let mut message = handle
+        .policy()
+        .add(SRC_NET, 64, DST_NET, 64)
+        .direction(XFRM_POLICY_OUT)
+        .selector_protocol(IPPROTO_TCP)
+        .selector_protocol_src_port_masked(1024, 0xfc00)
+        .into_message();
+    let buf = encode_message(&mut message);
+
+    let sel = &buf[SELECTOR..];
+    assert_eq!(sel[32..40], [0, 0, 0, 0, 0x04, 0x00, 0xfc, 0x00]);
+
+    let decoded = selector(&decode_message(&buf).unwrap());
+    assert_eq!((decoded.sport, decoded.sport_mask), (1024, 0xfc00));
+    assert_eq!((decoded.dport, decoded.dport_mask), (0, 0));
+}
+
+#[tokio::test]
+async fn modify_in_place_missing_policy() {
+    let (handle, mock) = mock_handle();
+    mock.respond(|_| vec![MockController::error(ENOENT)]);
+
+    let result = handle
+        .policy()
+        .modify_in_place(9, |info| info.priority = 10)
+        .await;
+
+    assert!(matches!(result, Err(Error::NotFound)), "{:?}", result);
+    // Only the get was sent.
+    assert_eq!(mock.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn append_templates_missing_policy() {
+    let (handle, mock) = mock_handle();
+    mock.respond(|_| vec![MockController::error(ENOENT)]);
+
+    let result = handle.policy().append_templates(9, Vec::new()).await;
+
+    assert!(matches!(result, Err(Error::NotFound)), "{:?}", result);
+    assert_eq!(mock.sent().len(), 1);
+}
+
+fn priority(message: &NetlinkMessage<XfrmMessage>) -> u32 {
+    match &message.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddPolicy(msg)) => msg.user_policy_info.priority,
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn default_priority_out_ranks_the_destination() {
+    let (handle, _) = mock_handle();
+    let add = |dst_prefix_len| {
+        handle
+            .policy()
+            .add(SRC_NET, 64, DST_NET, dst_prefix_len)
+            .direction(XFRM_POLICY_OUT)
+            .selector_protocol(IPPROTO_TCP)
+            .default_priority()
+            .unwrap()
+            .into_message()
+    };
+
+    // 0x40000 - (48 << 10 + 64 << 2 + 1)
+    assert_eq!(priority(&add(48)), 212735);
+    // A narrower destination wins, whatever the source.
+    assert!(priority(&add(56)) < priority(&add(48)));
+}
+
+#[tokio::test]
+async fn default_priority_in_ranks_the_source() {
+    let (handle, _) = mock_handle();
+    let add = |src_prefix_len| {
+        handle
+            .policy()
+            .add(SRC_NET, src_prefix_len, DST_NET, 48)
+            .direction(XFRM_POLICY_IN)
+            .selector_protocol(IPPROTO_TCP)
+            .default_priority()
+            .unwrap()
+            .into_message()
+    };
+
+    // 0x40000 - (64 << 10 + 48 << 2 + 1)
+    assert_eq!(priority(&add(64)), 196415);
+    assert!(priority(&add(72)) < priority(&add(64)));
+}
+
+#[tokio::test]
+async fn default_priority_fwd_ranks_the_source() {
+    let (handle, _) = mock_handle();
+    let add = |src_prefix_len, dst_prefix_len| {
+        handle
+            .policy()
+            .add(SRC_NET, src_prefix_len, DST_NET, dst_prefix_len)
+            .direction(XFRM_POLICY_FWD)
+            .default_priority()
+            .unwrap()
+            .into_message()
+    };
+
+    // 0x40000 - (64 << 10 + 48 << 2)
+    assert_eq!(priority(&add(64, 48)), 196416);
+    // The source prefix outweighs a much narrower destination.
+    assert!(priority(&add(65, 0)) < priority(&add(64, 128)));
+}
+
+#[tokio::test]
+async fn default_priority_needs_a_direction() {
+    let (handle, _) = mock_handle();
+
+    let result = handle
+        .policy()
+        .add(SRC_NET, 64, DST_NET, 48)
+        .default_priority();
+
+    assert!(matches!(result, Err(Error::MissingDirection)));
+}
+
+#[test]
+fn template_share_mode() {
+    let template = UserTemplateBuilder::new(SRC_NET, DST_NET)
+        .share(ShareMode::Unique)
+        .build();
+    assert_eq!(template.share, 3);
+
+    let template = UserTemplateBuilder::new(SRC_NET, DST_NET).share(1).build();
+    assert_eq!(ShareMode::try_from(template.share), Ok(ShareMode::Session));
+}
+
+// An XFRM_MSG_NEWPOLICY for 10.0.0.0/24 -> 10.0.1.0/24 dir out, as returned by a get,
+// with a comp (cpi 0) and an esp (spi 0x1000) template in tunnel mode.
+#[cfg(target_endian = "little")]
+const POLICY_WITH_TWO_TEMPLATES: &[&str] = &[
+    // nlmsghdr
+    "3c010000130000000000000000000000",
+    // xfrm_userpolicy_info: selector
+    "0a0001000000000000000000000000000a000000000000000000000000000000",
+    "000000000000000002001818000000000000000000000000",
+    // lifetime_cfg
+    "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    // lifetime_cur
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    // priority, index, dir, action, flags, share
+    "64000000090000000100000000000000",
+    // XFRMA_TMPL
+    "84000500",
+    // xfrm_user_tmpl: comp
+    "c0000202000000000000000000000000000000006c00000002000000c0000201",
+    "0000000000000000000000000100000001000000ffffffffffffffffffffffff",
+    // xfrm_user_tmpl: esp
+    "c0000202000000000000000000000000000010003200000002000000c0000201",
+    "0000000000000000000000000100000001000000ffffffffffffffffffffffff",
+];
+
+#[cfg(target_endian = "little")]
+#[test]
+fn templates_of_a_returned_policy() {
+    let buf = hex::decode(POLICY_WITH_TWO_TEMPLATES.concat()).unwrap();
+    let msg = match decode_message(&buf).unwrap().payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddPolicy(msg)) => msg,
+        payload => panic!("unexpected payload {:?}", payload),
+    };
+
+    let templates = msg.templates();
+    assert_eq!(templates.len(), 2);
+
+    assert_eq!(templates[0].id.proto, IPPROTO_COMP);
+    assert_eq!(templates[0].id.spi, 0);
+    assert_eq!(templates[1].id.proto, IPPROTO_ESP);
+    assert_eq!(templates[1].id.spi, 0x1000);
+    for template in templates {
+        assert_eq!(template.id.daddr, Address::from_ip(&GW_DST));
+        assert_eq!(template.saddr, Address::from_ip(&GW_SRC));
+        assert_eq!(template.reqid, 1);
+        assert_eq!(XfrmMode::from(template.mode), XfrmMode::Tunnel);
+    }
+    assert!(msg.is_tunnel());
+}
+
+#[tokio::test]
+async fn add_with_templates_uses_the_outermost_template() {
+    let (handle, mock) = mock_handle();
+    let inner_src = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    let inner_dst = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1));
+    let comp = UserTemplateBuilder::new(inner_src, inner_dst)
+        .protocol(IPPROTO_COMP)
+        .build();
+    let esp = UserTemplateBuilder::new(GW_SRC, GW_DST)
+        .protocol(IPPROTO_ESP)
+        .build();
+
+    handle
+        .policy()
+        .add_with_templates(XFRM_POLICY_OUT, vec![comp, esp])
+        .unwrap()
+        .execute()
+        .await
+        .unwrap();
+
+    let sent = mock.sent();
+    let sel = selector(&sent[0]);
+    assert_eq!(sel.saddr, Address::from_ip(&GW_SRC));
+    assert_eq!(sel.daddr, Address::from_ip(&GW_DST));
+    assert_eq!((sel.prefixlen_s, sel.prefixlen_d), (32, 32));
+
+    match &sent[0].payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddPolicy(msg)) => {
+            assert_eq!(msg.user_policy_info.direction, XFRM_POLICY_OUT);
+            assert_eq!(msg.templates(), [comp, esp]);
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn add_with_templates_needs_a_template() {
+    let (handle, _) = mock_handle();
+
+    let result = handle
+        .policy()
+        .add_with_templates(XFRM_POLICY_OUT, Vec::new());
+
+    assert!(
+        matches!(result, Err(Error::InvalidSelector { .. })),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
+
+#[tokio::test]
+async fn tunnel_policy_selector_and_template_addresses() {
+    let (handle, _) = mock_handle();
+    let template = UserTemplateBuilder::new(GW_SRC, GW_DST)
+        .protocol(IPPROTO_ESP)
+        .mode(XFRM_MODE_TUNNEL)
+        .reqid(1)
+        .build();
+    let mut message = handle
+        .policy()
+        .add(SRC_NET, 48, DST_NET, 48)
+        .direction(XFRM_POLICY_OUT)
+        .add_template(template)
+        .into_message();
+
+    // The inner IPv6 subnets are matched, the outer IPv4 gateways carry the traffic.
+    let decoded = decode_message(&encode_message(&mut message)).unwrap();
+    let sel = selector(&decoded);
+    assert_eq!(sel.saddr, Address::from_ip(&SRC_NET));
+    assert_eq!(sel.daddr, Address::from_ip(&DST_NET));
+    assert_eq!((sel.prefixlen_s, sel.prefixlen_d), (48, 48));
+    assert_eq!(sel.family, AF_INET6);
+
+    match &decoded.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddPolicy(msg)) => {
+            let templates = msg.templates();
+            assert_eq!(templates.len(), 1);
+            assert_eq!(templates[0].saddr, Address::from_ip(&GW_SRC));
+            assert_eq!(templates[0].id.daddr, Address::from_ip(&GW_DST));
+            assert_eq!(templates[0].family, AF_INET);
+            assert!(msg.is_tunnel());
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+// An XFRM_MSG_NEWSPDINFO for 1 in, 2 out and 3 fwd policies and no socket policies,
+// with 8 hash buckets out of at most 1048576 and the default hash thresholds.
+#[cfg(target_endian = "little")]
+const SPDINFO: &[&str] = &[
+    // nlmsghdr, flags
+    "4c000000240000000000000000000000",
+    "00000000",
+    // XFRMA_SPD_INFO
+    "1c000100010000000200000003000000000000000000000000000000",
+    // XFRMA_SPD_HINFO
+    "0c0002000800000000001000",
+    // XFRMA_SPD_IPV4_HTHRESH, XFRMA_SPD_IPV6_HTHRESH
+    "0600030020200000",
+    "0600040080800000",
+];
+
+#[cfg(target_endian = "little")]
+#[test]
+fn spdinfo_accessors() {
+    let buf = hex::decode(SPDINFO.concat()).unwrap();
+    let info = match decode_message(&buf).unwrap().payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::NewSpdInfo(info)) => info,
+        payload => panic!("unexpected payload {:?}", payload),
+    };
+
+    assert_eq!(info.count(), Some((1, 2, 3)));
+    assert_eq!(info.socket_count(), Some((0, 0, 0)));
+    assert_eq!(info.hash_count(), Some(8));
+    assert_eq!(info.hash_max(), Some(1_048_576));
+    assert_eq!(info.hthresh4(), Some((32, 32)));
+    assert_eq!(info.hthresh6(), Some((128, 128)));
+}
+
+// Offsets of the template modes in POLICY_WITH_TWO_TEMPLATES.
+#[cfg(target_endian = "little")]
+const TEMPLATE_MODES: [usize; 2] = [236, 300];
+
+#[cfg(target_endian = "little")]
+#[test]
+fn template_modes_of_a_returned_policy() {
+    let modes = [
+        (0, XfrmMode::Transport),
+        (1, XfrmMode::Tunnel),
+        (2, XfrmMode::RouteOptimization),
+        (3, XfrmMode::InTrigger),
+        (4, XfrmMode::Beet),
+        (5, XfrmMode::IpTfs),
+        (42, XfrmMode::Unknown(42)),
+    ];
+    let mut buf = hex::decode(POLICY_WITH_TWO_TEMPLATES.concat()).unwrap();
+    for offset in TEMPLATE_MODES {
+        assert_eq!(buf[offset], 1);
+    }
+
+    for (value, mode) in modes {
+        // Only the outer template changes, the inner one stays in transport mode.
+        buf[TEMPLATE_MODES[0]] = 0;
+        buf[TEMPLATE_MODES[1]] = value;
+        let msg = match decode_message(&buf).unwrap().payload {
+            NetlinkPayload::InnerMessage(XfrmMessage::AddPolicy(msg)) => msg,
+            payload => panic!("unexpected payload {:?}", payload),
+        };
+
+        let templates = msg.templates();
+        assert_eq!(XfrmMode::from(templates[0].mode), XfrmMode::Transport);
+        assert_eq!(XfrmMode::from(templates[1].mode), mode);
+        assert_eq!(msg.is_tunnel(), mode == XfrmMode::Tunnel);
+    }
+}
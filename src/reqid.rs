@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: MIT
+
+use futures::{future, stream::TryStreamExt};
+use std::collections::HashSet;
+
+use crate::{Error, Handle};
+use netlink_packet_xfrm::XfrmAttrs;
+
+/// Hands out request ids (reqids) that are not used by any existing state or policy.
+///
+/// The kernel doesn't allocate reqids, the key manager picks them. `refresh()` dumps
+/// the SAD and SPD to find the reqids already in use, and `allocate()` then returns
+/// the lowest free reqid in the range. Allocated reqids are remembered until they are
+/// released or the allocator is refreshed, so states can be installed in between.
+///
+/// This only avoids collisions with what is installed when `refresh()` runs, and with
+/// other users of the same allocator. It can't protect against other key managers.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ReqidAllocator {
+    handle: Handle,
+    min: u32,
+    max: u32,
+    in_use: HashSet<u32>,
+}
+
+impl ReqidAllocator {
+    /// Allocate reqids between `min` and `max` (inclusive).
+    /// A reqid of 0 means "any" to the kernel, so it is never allocated.
+    pub fn new(handle: Handle, min: u32, max: u32) -> Self {
+        ReqidAllocator {
+            handle,
+            min: min.max(1),
+            max,
+            in_use: HashSet::new(),
+        }
+    }
+
+    /// Dump the states and policies to find the reqids currently in use.
+    /// This replaces the reqids previously allocated or found.
+    pub async fn refresh(&mut self) -> Result<(), Error> {
+        let in_use = self
+            .handle
+            .state()
+            .get_dump()
+            .execute()
+            .try_fold(HashSet::new(), |mut in_use, state| {
+                in_use.insert(state.user_sa_info.reqid);
+                future::ready(Ok(in_use))
+            })
+            .await?;
+
+        let in_use = self
+            .handle
+            .policy()
+            .get_dump()
+            .execute()
+            .try_fold(in_use, |mut in_use, policy| {
+                for nla in &policy.nlas {
+                    if let XfrmAttrs::Template(templates) = nla {
+                        in_use.extend(templates.iter().map(|tmpl| tmpl.reqid));
+                    }
+                }
+                future::ready(Ok(in_use))
+            })
+            .await?;
+
+        self.in_use = in_use;
+        Ok(())
+    }
+
+    /// Return the lowest free reqid in the range and mark it as used,
+    /// or `None` if the range is exhausted.
+    pub fn allocate(&mut self) -> Option<u32> {
+        let reqid = (self.min..=self.max).find(|reqid| !self.in_use.contains(reqid))?;
+        self.in_use.insert(reqid);
+        Some(reqid)
+    }
+
+    /// Make a reqid available again, e.g. after its states and policies were deleted.
+    pub fn release(&mut self, reqid: u32) {
+        self.in_use.remove(&reqid);
+    }
+
+    /// Mark a reqid as used, e.g. one chosen by hand or from configuration.
+    pub fn reserve(&mut self, reqid: u32) {
+        self.in_use.insert(reqid);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock_handle;
+    use netlink_packet_core::NetlinkMessage;
+    use netlink_packet_xfrm::{
+        policy, state, UserTemplate, XfrmMessage, XFRM_MSG_GETPOLICY, XFRM_MSG_GETSA,
+    };
+
+    #[tokio::test]
+    async fn allocate_skips_reserved_reqids() {
+        let (handle, _) = mock_handle();
+        let mut reqids = ReqidAllocator::new(handle, 0, 10);
+
+        // 0 means any reqid, it's never handed out.
+        assert_eq!(reqids.allocate(), Some(1));
+        reqids.reserve(2);
+        reqids.reserve(3);
+        assert_eq!(reqids.allocate(), Some(4));
+
+        reqids.release(1);
+        reqids.release(3);
+        assert_eq!(reqids.allocate(), Some(1));
+        assert_eq!(reqids.allocate(), Some(3));
+        assert_eq!(reqids.allocate(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn allocate_until_exhausted() {
+        let (handle, _) = mock_handle();
+        let mut reqids = ReqidAllocator::new(handle, 100, 102);
+        reqids.reserve(101);
+
+        assert_eq!(reqids.allocate(), Some(100));
+        assert_eq!(reqids.allocate(), Some(102));
+        assert_eq!(reqids.allocate(), None);
+
+        reqids.release(101);
+        assert_eq!(reqids.allocate(), Some(101));
+        assert_eq!(reqids.allocate(), None);
+    }
+
+    #[tokio::test]
+    async fn refresh_finds_the_reqids_in_use() {
+        let (handle, mock) = mock_handle();
+        mock.respond(|req| match req.payload.message_type() {
+            XFRM_MSG_GETSA => [1, 3]
+                .iter()
+                .map(|&reqid| {
+                    let mut msg = state::ModifyMessage::default();
+                    msg.user_sa_info.reqid = reqid;
+                    NetlinkMessage::from(XfrmMessage::AddSa(msg))
+                })
+                .collect(),
+            XFRM_MSG_GETPOLICY => [4, 6]
+                .iter()
+                .map(|&reqid| {
+                    let tmpl = UserTemplate {
+                        reqid,
+                        ..Default::default()
+                    };
+                    let mut msg = policy::ModifyMessage::default();
+                    msg.nlas.push(XfrmAttrs::Template(vec![tmpl]));
+                    NetlinkMessage::from(XfrmMessage::AddPolicy(msg))
+                })
+                .collect(),
+            message_type => panic!("unexpected request {}", message_type),
+        });
+
+        let mut reqids = ReqidAllocator::new(handle, 1, 7);
+        reqids.reserve(2);
+        reqids.refresh().await.unwrap();
+
+        // The reserved reqid is forgotten, the ones found are kept.
+        assert_eq!(reqids.allocate(), Some(2));
+        assert_eq!(reqids.allocate(), Some(5));
+        assert_eq!(reqids.allocate(), Some(7));
+        assert_eq!(reqids.allocate(), None);
+    }
+}
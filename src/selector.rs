@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+
+use crate::{link::ifindex_by_name, Error};
+use netlink_packet_xfrm::Selector;
+
+/// A builder for xfrm selectors, the traffic matched by a policy or bound to a state.
+/// This is equivalent to the `SELECTOR` portion of the `ip xfrm` commands.
+///
+/// The `selector_*` methods of the request builders are shortcuts for these, and a
+/// whole selector can be set at once with their `selector` method.
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct SelectorBuilder {
+    selector: Selector,
+}
+
+impl SelectorBuilder {
+    /// The family is taken from the addresses, so both must be of the same family.
+    pub fn new(src_addr: IpAddr, src_prefix_len: u8, dst_addr: IpAddr, dst_prefix_len: u8) -> Self {
+        SelectorBuilder::default().addresses(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+    }
+
+    // Replaces the addresses, and the family with theirs. IPv6 selectors match on
+    // addresses, protocol, ports and device only: the kernel's xfrm_selector has no
+    // field for the flow label or traffic class.
+    pub fn addresses(
+        mut self,
+        src_addr: IpAddr,
+        src_prefix_len: u8,
+        dst_addr: IpAddr,
+        dst_prefix_len: u8,
+    ) -> Self {
+        self.selector.source_prefix(&src_addr, src_prefix_len);
+        self.selector.destination_prefix(&dst_addr, dst_prefix_len);
+        self
+    }
+    pub fn protocol(mut self, proto: u8) -> Self {
+        self.selector.proto = proto;
+        self
+    }
+    pub fn protocol_src_port(self, port: u16) -> Self {
+        self.protocol_src_port_masked(port, u16::MAX)
+    }
+    pub fn protocol_dst_port(self, port: u16) -> Self {
+        self.protocol_dst_port_masked(port, u16::MAX)
+    }
+    // Match a range of ports by only comparing the bits set in the mask, e.g. port 1024 with
+    // mask 0xfc00 matches ports 1024-2047. Only ranges aligned to a power of two can be
+    // expressed this way, arbitrary ranges need multiple selectors.
+    pub fn protocol_src_port_masked(mut self, port: u16, mask: u16) -> Self {
+        self.selector.sport = port;
+        self.selector.sport_mask = mask;
+        self
+    }
+    pub fn protocol_dst_port_masked(mut self, port: u16, mask: u16) -> Self {
+        self.selector.dport = port;
+        self.selector.dport_mask = mask;
+        self
+    }
+    // The ICMP type and code are carried in the source and destination ports.
+    pub fn protocol_type(self, proto_type: u8) -> Self {
+        self.protocol_src_port(proto_type as u16)
+    }
+    pub fn protocol_code(self, proto_code: u8) -> Self {
+        self.protocol_dst_port(proto_code as u16)
+    }
+    // The GRE key is split across the source (high bits) and destination (low bits) ports.
+    pub fn protocol_gre_key(self, gre_key: u32) -> Self {
+        self.protocol_src_port((gre_key >> 16) as u16)
+            .protocol_dst_port((gre_key & 0xffff) as u16)
+    }
+    // Clear the protocol, ports (or type/code, GRE key) and their masks back to matching
+    // any traffic, e.g. when a request cloned from a template needs a broader selector.
+    pub fn any_protocol(self) -> Self {
+        self.protocol(0)
+            .protocol_src_port_masked(0, 0)
+            .protocol_dst_port_masked(0, 0)
+    }
+    pub fn dev_id(mut self, id: u32) -> Self {
+        self.selector.ifindex = id as i32;
+        self
+    }
+    /// Same as `dev_id`, looking up the index of the named interface.
+    /// Fails with `Error::InterfaceNotFound` if there is no such interface, rather
+    /// than leaving an ifindex of 0, which would match any interface.
+    pub fn dev(self, name: &str) -> Result<Self, Error> {
+        Ok(self.dev_id(ifindex_by_name(name)?))
+    }
+
+    /// Build the selector, ready to be passed to the `selector` method of a request.
+    pub fn build(self) -> Selector {
+        self.selector
+    }
+}
+
+impl From<Selector> for SelectorBuilder {
+    fn from(selector: Selector) -> SelectorBuilder {
+        SelectorBuilder { selector }
+    }
+}
+
+impl From<SelectorBuilder> for Selector {
+    fn from(builder: SelectorBuilder) -> Selector {
+        builder.build()
+    }
+}
@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::Error;
+
+// SPIs 1-255 are reserved by IANA for future use, and 0 is reserved for local use (RFC 4303).
+const SPI_RESERVED_MAX: u32 = 255;
+// IPComp uses a 16-bit CPI in place of the SPI, 0-255 are reserved as well (RFC 3173).
+const CPI_MAX: u32 = 0xffff;
+const IPPROTO_COMP: u8 = 108;
+
+/// A Security Parameter Index. Displayed in the `0x`-prefixed, zero padded
+/// hex form used by iproute2 (e.g. `0x00001000`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Spi(pub u32);
+
+impl fmt::Display for Spi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+impl From<u32> for Spi {
+    fn from(spi: u32) -> Self {
+        Spi(spi)
+    }
+}
+
+impl From<Spi> for u32 {
+    fn from(spi: Spi) -> u32 {
+        spi.0
+    }
+}
+
+/// An inclusive range of SPIs to allocate from, see `StateAllocSpiRequest::range`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpiRange {
+    min: u32,
+    max: u32,
+}
+
+impl SpiRange {
+    /// Create a range from `min` to `max` (inclusive). The range must not be empty,
+    /// and must not include the reserved SPIs 0-255.
+    pub fn new(min: u32, max: u32) -> Result<Self, Error> {
+        if min > max {
+            return Err(Error::InvalidSpiRange(min, max));
+        }
+        if min <= SPI_RESERVED_MAX {
+            return Err(Error::InvalidSpi(min));
+        }
+        Ok(SpiRange { min, max })
+    }
+
+    pub fn min(&self) -> Spi {
+        Spi(self.min)
+    }
+
+    pub fn max(&self) -> Spi {
+        Spi(self.max)
+    }
+
+    pub fn contains(&self, spi: Spi) -> bool {
+        (self.min..=self.max).contains(&spi.0)
+    }
+
+    /// Iterate over every SPI in the range, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Spi> {
+        RangeInclusive::new(self.min, self.max).map(Spi)
+    }
+}
+
+// Check an SPI range against the values allowed for the protocol: SPIs 0-255 are
+// reserved for ESP and AH, and COMP CPIs are also limited to 16 bits.
+pub(crate) fn check_spi_range(protocol: u8, min: u32, max: u32) -> Result<(), Error> {
+    if min > max {
+        return Err(Error::InvalidSpiRange(min, max));
+    }
+    if protocol == IPPROTO_COMP {
+        if min <= SPI_RESERVED_MAX || max > CPI_MAX {
+            return Err(Error::InvalidCpi(if max > CPI_MAX { max } else { min }));
+        }
+    } else if min <= SPI_RESERVED_MAX {
+        return Err(Error::InvalidSpi(min));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const IPPROTO_ESP: u8 = 50;
+
+    #[test]
+    fn esp_range_starts_above_the_reserved_spis() {
+        assert_eq!(
+            check_spi_range(IPPROTO_ESP, 255, 0x1000),
+            Err(Error::InvalidSpi(255))
+        );
+        assert_eq!(check_spi_range(IPPROTO_ESP, 256, 0x1000), Ok(()));
+        assert_eq!(check_spi_range(IPPROTO_ESP, 256, u32::MAX), Ok(()));
+    }
+
+    #[test]
+    fn comp_range_is_limited_to_16_bits() {
+        assert_eq!(
+            check_spi_range(IPPROTO_COMP, 255, 0x1000),
+            Err(Error::InvalidCpi(255))
+        );
+        assert_eq!(check_spi_range(IPPROTO_COMP, 256, 0xffff), Ok(()));
+        assert_eq!(
+            check_spi_range(IPPROTO_COMP, 256, 0x10000),
+            Err(Error::InvalidCpi(0x10000))
+        );
+        // The upper bound is reported first.
+        assert_eq!(
+            check_spi_range(IPPROTO_COMP, 1, 0x10000),
+            Err(Error::InvalidCpi(0x10000))
+        );
+    }
+
+    #[test]
+    fn range_min_above_max() {
+        for protocol in [IPPROTO_ESP, IPPROTO_COMP] {
+            assert_eq!(
+                check_spi_range(protocol, 0x2000, 0x1000),
+                Err(Error::InvalidSpiRange(0x2000, 0x1000))
+            );
+        }
+    }
+
+    #[test]
+    fn spi_display() {
+        assert_eq!(Spi(0x1000).to_string(), "0x00001000");
+        assert_eq!(Spi(0).to_string(), "0x00000000");
+        assert_eq!(Spi(u32::MAX).to_string(), "0xffffffff");
+    }
+
+    #[test]
+    fn spi_range_contains() {
+        let range = SpiRange::new(0x100, 0x1ff).unwrap();
+        assert!(!range.contains(Spi(0xff)));
+        assert!(range.contains(Spi(0x100)));
+        assert!(range.contains(Spi(0x1ff)));
+        assert!(!range.contains(Spi(0x200)));
+        assert_eq!(range.iter().count(), 0x100);
+
+        let single = SpiRange::new(0x1000, 0x1000).unwrap();
+        assert_eq!(single.iter().collect::<Vec<_>>(), vec![Spi(0x1000)]);
+    }
+
+    #[test]
+    fn spi_range_rejected() {
+        assert_eq!(
+            SpiRange::new(0x2000, 0x1000),
+            Err(Error::InvalidSpiRange(0x2000, 0x1000))
+        );
+        assert_eq!(SpiRange::new(0, 0x1000), Err(Error::InvalidSpi(0)));
+        assert_eq!(SpiRange::new(255, 0x1000), Err(Error::InvalidSpi(255)));
+        assert!(SpiRange::new(256, 0x1000).is_ok());
+    }
+}
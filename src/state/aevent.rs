@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MIT
+
+use futures::stream::StreamExt;
+use std::net::IpAddr;
+
+use crate::{
+    codec::with_attributes, lifetime::lifetime_current_nla, try_nl, try_xfrmnl, Error, Handle,
+};
+use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REPLACE, NLM_F_REQUEST};
+use netlink_packet_xfrm::{
+    constants::*,
+    monitor::{GetAsyncEventMessage, NewAsyncEventMessage},
+    Address, Mark, Replay, XfrmAttrs, XfrmMessage,
+};
+
+/// A request to get the async event state (replay counters, current lifetime and
+/// event thresholds) of an xfrm state. There is no direct `ip xfrm` equivalent.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct StateGetAeRequest {
+    handle: Handle,
+    message: GetAsyncEventMessage,
+    // GetAsyncEventMessage has no attribute list, see codec::with_attributes.
+    nlas: Vec<XfrmAttrs>,
+    netlink_flags: u16,
+}
+
+impl StateGetAeRequest {
+    pub(crate) fn new(handle: Handle, src_addr: IpAddr, dst_addr: IpAddr) -> Self {
+        let mut message = GetAsyncEventMessage::default();
+
+        message.id.sa_id.destination(&dst_addr);
+        message.id.saddr = Address::from_ip(&src_addr);
+        message.id.flags = XFRM_AE_RVAL | XFRM_AE_LVAL | XFRM_AE_RTHR | XFRM_AE_ETHR;
+
+        StateGetAeRequest {
+            handle,
+            message,
+            nlas: Vec::new(),
+            netlink_flags: 0,
+        }
+    }
+
+    pub fn protocol(mut self, protocol: u8) -> Self {
+        self.message.id.sa_id.proto = protocol;
+        self
+    }
+    pub fn spi(mut self, spi: u32) -> Self {
+        self.message.id.sa_id.spi = spi;
+        self
+    }
+    pub fn mark(mut self, mark: u32, mask: u32) -> Self {
+        self.nlas.push(XfrmAttrs::Mark(Mark { value: mark, mask }));
+        self
+    }
+
+    /// Extra netlink header flags for the async event get, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
+        let StateGetAeRequest {
+            message,
+            nlas,
+            netlink_flags,
+            ..
+        } = self;
+
+        let message = with_attributes(XfrmMessage::GetAsyncEvent(message), &nlas);
+        let mut req = NetlinkMessage::from(message);
+        req.header.flags = NLM_F_REQUEST;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<NewAsyncEventMessage, Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
+
+        let mut response = handle.request(req)?;
+
+        // A successful get async event request returns with a NewAsyncEvent response.
+        if let Some(msg) = response.next().await {
+            return Ok(try_xfrmnl!(msg, XfrmMessage::NewAsyncEvent));
+        }
+        Err(Error::RequestFailed)
+    }
+
+    /// Return a mutable reference to the request message.
+    pub fn message_mut(&mut self) -> &mut GetAsyncEventMessage {
+        &mut self.message
+    }
+}
+
+/// A request to set the async event state (replay counters, current lifetime) of an xfrm state.
+/// There is no direct `ip xfrm` equivalent.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct StateSetAeRequest {
+    handle: Handle,
+    message: NewAsyncEventMessage,
+    netlink_flags: u16,
+}
+
+impl StateSetAeRequest {
+    pub(crate) fn new(handle: Handle, src_addr: IpAddr, dst_addr: IpAddr) -> Self {
+        let mut message = NewAsyncEventMessage::default();
+
+        message.id.sa_id.destination(&dst_addr);
+        message.id.saddr = Address::from_ip(&src_addr);
+
+        StateSetAeRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
+    }
+
+    pub fn protocol(mut self, protocol: u8) -> Self {
+        self.message.id.sa_id.proto = protocol;
+        self
+    }
+    pub fn spi(mut self, spi: u32) -> Self {
+        self.message.id.sa_id.spi = spi;
+        self
+    }
+    pub fn reqid(mut self, reqid: u32) -> Self {
+        self.message.id.reqid = reqid;
+        self
+    }
+    pub fn mark(mut self, mark: u32, mask: u32) -> Self {
+        self.message
+            .nlas
+            .push(XfrmAttrs::Mark(Mark { value: mark, mask }));
+        self
+    }
+
+    // Only for states without ESN, the replay window bitmap is limited to 32 packets.
+    pub fn replay_state(mut self, seq: u32, offload_seq: u32, bitmap: u32) -> Self {
+        self.message.nlas.push(XfrmAttrs::ReplayState(Replay {
+            seq,
+            oseq: offload_seq,
+            bitmap,
+        }));
+        self.message.id.flags |= XFRM_AE_RVAL;
+        self
+    }
+
+    pub fn lifetime_current(
+        mut self,
+        bytes: u64,
+        packets: u64,
+        add_time: u64,
+        use_time: u64,
+    ) -> Self {
+        self.message
+            .nlas
+            .push(lifetime_current_nla(bytes, packets, add_time, use_time));
+        self.message.id.flags |= XFRM_AE_LVAL;
+        self
+    }
+
+    // Send an async event once the replay counter has advanced by this many packets.
+    pub fn replay_thresh(mut self, packets: u32) -> Self {
+        self.message.nlas.push(XfrmAttrs::ReplayThreshold(packets));
+        self.message.id.flags |= XFRM_AE_RTHR;
+        self
+    }
+
+    // Send an async event at most this often (in units of 100ms) while the replay
+    // counter changes, even if the replay threshold hasn't been reached.
+    pub fn etimer_thresh(mut self, interval: u32) -> Self {
+        self.message
+            .nlas
+            .push(XfrmAttrs::EventTimeThreshold(interval));
+        self.message.id.flags |= XFRM_AE_ETHR;
+        self
+    }
+
+    /// Extra netlink header flags for the async event update, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
+        let StateSetAeRequest {
+            message,
+            netlink_flags,
+            ..
+        } = self;
+
+        // The kernel refuses a NEWAE message unless it is marked as a replacement.
+        let mut req = NetlinkMessage::from(XfrmMessage::NewAsyncEvent(message));
+        req.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_REPLACE;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
+
+        let mut response = handle.request(req)?;
+
+        while let Some(msg) = response.next().await {
+            try_nl!(msg);
+        }
+        Ok(())
+    }
+
+    /// Return a mutable reference to the request message.
+    pub fn message_mut(&mut self) -> &mut NewAsyncEventMessage {
+        &mut self.message
+    }
+}
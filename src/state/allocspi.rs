@@ -2,12 +2,14 @@
 
 use futures::{
     future::{self, Either},
-    stream::{StreamExt, TryStream},
+    stream::{StreamExt, TryStream, TryStreamExt},
     FutureExt,
 };
 use std::net::IpAddr;
 
-use crate::{try_xfrmnl, Error, Handle};
+use crate::{
+    errors::ENOENT, spi::check_spi_range, try_xfrmnl, Error, Handle, SpiRange, XfrmInterfaceId,
+};
 use netlink_packet_core::{NetlinkMessage, NLM_F_REQUEST};
 use netlink_packet_xfrm::{
     state::{AllocSpiMessage, ModifyMessage},
@@ -15,10 +17,13 @@ use netlink_packet_xfrm::{
 };
 
 /// A request to allocate a SPI for an xfrm state. This is equivalent to the `ip xfrm state allocspi` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateAllocSpiRequest {
     handle: Handle,
     message: AllocSpiMessage,
+    netlink_flags: u16,
+    requested_spi: Option<u32>,
 }
 
 impl StateAllocSpiRequest {
@@ -29,17 +34,40 @@ impl StateAllocSpiRequest {
         message.spi_info.info.destination(&dst_addr);
         message.spi_info.info.id.spi = 0;
 
-        StateAllocSpiRequest { handle, message }
+        StateAllocSpiRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+            requested_spi: None,
+        }
     }
 
+    // The fields are set directly: the UserSpiInfo setters silently clamp COMP
+    // ranges to 0xffff, hiding them from check_range.
     pub fn protocol(mut self, protocol: u8) -> Self {
-        self.message.spi_info.protocol(protocol);
+        self.message.spi_info.info.id.proto = protocol;
         self
     }
     pub fn spi_range(mut self, spi_min: u32, spi_max: u32) -> Self {
-        self.message.spi_info.spi_range(spi_min, spi_max);
+        self.message.spi_info.min = spi_min;
+        self.message.spi_info.max = spi_max;
         self
     }
+    /// Same as `spi_range`, with a range that has already been validated.
+    /// For COMP, the range is also checked against the 16-bit CPI range on `execute()`.
+    pub fn range(self, range: SpiRange) -> Self {
+        self.spi_range(range.min().into(), range.max().into())
+    }
+    /// Reserve exactly this SPI, equivalent to `spi_range(spi, spi)`.
+    /// SPIs 0-255 are reserved by RFC 4303 and are rejected. For COMP, the SPI is a
+    /// 16-bit CPI, and values above 0xffff are rejected as well, so set `protocol()`
+    /// before calling this.
+    pub fn spi(self, spi: u32) -> Result<Self, Error> {
+        check_spi_range(self.message.spi_info.info.id.proto, spi, spi)?;
+        let mut request = self.spi_range(spi, spi);
+        request.requested_spi = Some(spi);
+        Ok(request)
+    }
     pub fn mode(mut self, mode: u8) -> Self {
         self.message.spi_info.info.mode = mode;
         self
@@ -64,19 +92,53 @@ impl StateAllocSpiRequest {
         self.message.nlas.push(XfrmAttrs::IfId(ifid));
         self
     }
+    /// Same as `ifid`, with a typed xfrm interface id.
+    pub fn interface_id(self, ifid: XfrmInterfaceId) -> Self {
+        self.ifid(ifid.0)
+    }
 
-    /// Execute the request
-    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+    /// Extra netlink header flags for the SPI allocation, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateAllocSpiRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::AllocSpi(message));
         req.header.flags = NLM_F_REQUEST;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    // Validate the SPI range against the protocol, if one was set. The kernel rejects
+    // COMP ranges above 0xffff with a bare EINVAL.
+    fn check_range(&self) -> Result<(), Error> {
+        let spi_info = &self.message.spi_info;
+        if spi_info.min == 0 && spi_info.max == 0 {
+            return Ok(());
+        }
+        check_spi_range(spi_info.info.id.proto, spi_info.min, spi_info.max)
+    }
+
+    /// Execute the request
+    ///
+    /// The SPI range is checked against the protocol first: the request fails with
+    /// `Error::InvalidCpi` if the protocol is COMP and the range isn't within the
+    /// 16-bit CPI range.
+    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+        let mut handle = self.handle.clone();
+        let range_check = self.check_range();
+        let req = self.into_message();
 
         // A successful alloc spi request returns with an Add/ModifyMessage response.
-        match handle.request(req) {
+        match range_check.and_then(|_| handle.request(req)) {
             Ok(response) => {
                 Either::Left(response.map(move |msg| Ok(try_xfrmnl!(msg, XfrmMessage::AddSa))))
             }
@@ -84,6 +146,24 @@ impl StateAllocSpiRequest {
         }
     }
 
+    /// Execute the request and return the allocated SPI.
+    /// If a single SPI was requested with `spi()` and it is already in use,
+    /// `Error::SpiUnavailable` is returned.
+    pub async fn allocate(self) -> Result<u32, Error> {
+        let requested_spi = self.requested_spi;
+        let mut response = self.execute();
+
+        match response.try_next().await {
+            Ok(Some(state)) => Ok(state.user_sa_info.id.spi),
+            Ok(None) => Err(Error::RequestFailed),
+            // The kernel returns ENOENT rather than EEXIST when a requested SPI is in use.
+            Err(e) => match requested_spi {
+                Some(spi) if e.errno() == Some(ENOENT) => Err(Error::SpiUnavailable(spi)),
+                _ => Err(e),
+            },
+        }
+    }
+
     /// Return a mutable reference to the request message.
     pub fn message_mut(&mut self) -> &mut AllocSpiMessage {
         &mut self.message
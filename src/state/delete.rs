@@ -3,15 +3,17 @@
 use futures::stream::StreamExt;
 use std::net::IpAddr;
 
-use crate::{try_nl, Error, Handle};
+use crate::{try_nl, Error, Handle, SaKey};
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
-use netlink_packet_xfrm::{state::DelGetMessage, Address, Mark, XfrmAttrs, XfrmMessage};
+use netlink_packet_xfrm::{state::DelGetMessage, Address, Mark, UserSaId, XfrmAttrs, XfrmMessage};
 
 /// A request to delete xfrm state. This is equivalent to the `ip xfrm state delete` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateDeleteRequest {
     handle: Handle,
     message: DelGetMessage,
+    netlink_flags: u16,
 }
 
 impl StateDeleteRequest {
@@ -24,7 +26,24 @@ impl StateDeleteRequest {
 
         message.user_sa_id.destination(&dst_addr);
 
-        StateDeleteRequest { handle, message }
+        StateDeleteRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
+    }
+
+    pub(crate) fn from_id(handle: Handle, id: UserSaId) -> Self {
+        let message = DelGetMessage {
+            user_sa_id: id,
+            ..Default::default()
+        };
+
+        StateDeleteRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     pub fn protocol(mut self, protocol: u8) -> Self {
@@ -47,15 +66,30 @@ impl StateDeleteRequest {
         self
     }
 
-    /// Execute the request.
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Extra netlink header flags for the delete, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateDeleteRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::DeleteSa(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -66,14 +100,11 @@ impl StateDeleteRequest {
     }
 
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let StateDeleteRequest {
-            mut handle,
-            message,
-        } = self;
-
-        let mut req = NetlinkMessage::from(XfrmMessage::DeleteSa(message));
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
@@ -85,3 +116,14 @@ impl StateDeleteRequest {
         &mut self.message
     }
 }
+
+/// The outcome of `StateHandle::delete_where`.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct StateDeleteSummary {
+    /// The number of states deleted.
+    pub deleted: usize,
+    /// The states that matched but could not be deleted, e.g. because they
+    /// expired or were deleted by someone else in the meantime.
+    pub errors: Vec<(SaKey, Error)>,
+}
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT
+
+use futures::stream::StreamExt;
+use std::net::IpAddr;
+
+use crate::{codec::with_attributes, try_nl, Error, Handle};
+use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
+use netlink_packet_xfrm::{monitor::ExpireMessage, Mark, XfrmAttrs, XfrmMessage};
+
+/// A request to force an xfrm state to expire. There is no direct `ip xfrm` equivalent.
+///
+/// A soft expire only notifies the key managers (which typically triggers a rekey),
+/// while a hard expire also deletes the state immediately.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct StateExpireRequest {
+    handle: Handle,
+    message: ExpireMessage,
+    // ExpireMessage has no attribute list, see codec::with_attributes.
+    nlas: Vec<XfrmAttrs>,
+    netlink_flags: u16,
+}
+
+impl StateExpireRequest {
+    pub(crate) fn new(handle: Handle, src_addr: IpAddr, dst_addr: IpAddr) -> Self {
+        let mut message = ExpireMessage::default();
+
+        message.expire.state.source(&src_addr);
+        message.expire.state.destination(&dst_addr);
+
+        StateExpireRequest {
+            handle,
+            message,
+            nlas: Vec::new(),
+            netlink_flags: 0,
+        }
+    }
+
+    pub fn protocol(mut self, protocol: u8) -> Self {
+        self.message.expire.state.id.proto = protocol;
+        self
+    }
+    pub fn spi(mut self, spi: u32) -> Self {
+        self.message.expire.state.id.spi = spi;
+        self
+    }
+    pub fn mark(mut self, mark: u32, mask: u32) -> Self {
+        self.nlas.push(XfrmAttrs::Mark(Mark { value: mark, mask }));
+        self
+    }
+    // Defaults to a soft expire
+    pub fn hard(mut self, hard: bool) -> Self {
+        self.message.expire.hard = hard as u8;
+        self
+    }
+
+    /// Extra netlink header flags for the expire, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
+        let StateExpireRequest {
+            message,
+            nlas,
+            netlink_flags,
+            ..
+        } = self;
+
+        let message = with_attributes(XfrmMessage::Expire(message), &nlas);
+        let mut req = NetlinkMessage::from(message);
+        req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
+
+        let mut response = handle.request(req)?;
+
+        while let Some(message) = response.next().await {
+            try_nl!(message);
+        }
+        Ok(())
+    }
+
+    /// Return a mutable reference to the request message.
+    pub fn message_mut(&mut self) -> &mut ExpireMessage {
+        &mut self.message
+    }
+}
@@ -2,22 +2,29 @@
 
 use futures::stream::StreamExt;
 
+use super::{SadInfoMessageExt, StateGetSadInfoRequest};
 use crate::{try_nl, Error, Handle};
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
 use netlink_packet_xfrm::{state::FlushMessage, XfrmMessage};
 
 /// A request to flush xfrm policies. This is equivalent to the `ip xfrm policy flush` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateFlushRequest {
     handle: Handle,
     message: FlushMessage,
+    netlink_flags: u16,
 }
 
 impl StateFlushRequest {
     pub(crate) fn new(handle: Handle) -> Self {
         let message = FlushMessage::default();
 
-        StateFlushRequest { handle, message }
+        StateFlushRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     pub fn protocol(mut self, protocol: u8) -> Self {
@@ -25,15 +32,30 @@ impl StateFlushRequest {
         self
     }
 
-    /// Execute the request.
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Extra netlink header flags for the flush, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateFlushRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::FlushSa(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    pub async fn execute(self) -> Result<(), Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
         while let Some(message) = response.next().await {
@@ -42,15 +64,28 @@ impl StateFlushRequest {
         Ok(())
     }
 
+    /// Execute the request, and return how many states were removed.
+    ///
+    /// The kernel doesn't report it, so the SA count (`get_sadinfo`) is read before
+    /// and after the flush and the difference is returned. The count covers states of
+    /// every protocol, so states added or expiring at the same time (e.g. by a running
+    /// key manager) make the result inaccurate.
+    pub async fn execute_counting(self) -> Result<u32, Error> {
+        let sadinfo = StateGetSadInfoRequest::new(self.handle.clone());
+
+        let before = sadinfo.clone().execute().await?.count().unwrap_or(0);
+        self.execute().await?;
+        let after = sadinfo.execute().await?.count().unwrap_or(0);
+
+        Ok(before.saturating_sub(after))
+    }
+
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let StateFlushRequest {
-            mut handle,
-            message,
-        } = self;
-
-        let mut req = NetlinkMessage::from(XfrmMessage::FlushSa(message));
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
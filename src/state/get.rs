@@ -1,24 +1,30 @@
 // SPDX-License-Identifier: MIT
 
 use futures::{
+    executor::block_on_stream,
     future::{self, Either},
-    stream::{StreamExt, TryStream},
+    stream::{StreamExt, TryStream, TryStreamExt},
     FutureExt,
 };
+use std::collections::HashMap;
 use std::net::IpAddr;
 
-use crate::{try_xfrmnl, Error, Handle};
-use netlink_packet_core::{NetlinkMessage, NLM_F_DUMP, NLM_F_REQUEST};
+use crate::{address::address_to_ip_lossy, errors::ENOENT, try_xfrmnl, Error, Handle};
+use netlink_packet_core::{
+    NetlinkMessage, NetlinkPayload, NLM_F_DUMP, NLM_F_DUMP_INTR, NLM_F_REQUEST,
+};
 use netlink_packet_xfrm::{
     state::{DelGetMessage, GetDumpMessage, ModifyMessage},
     Address, AddressFilter, Mark, XfrmAttrs, XfrmMessage,
 };
 
 /// A request to get xfrm state. This is equivalent to the `ip xfrm state get` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateGetRequest {
     handle: Handle,
     message: DelGetMessage,
+    netlink_flags: u16,
 }
 
 impl StateGetRequest {
@@ -31,7 +37,11 @@ impl StateGetRequest {
 
         message.user_sa_id.destination(&dst_addr);
 
-        StateGetRequest { handle, message }
+        StateGetRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
     pub fn protocol(mut self, protocol: u8) -> Self {
@@ -54,43 +64,111 @@ impl StateGetRequest {
         self
     }
 
-    /// Execute the request
-    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+    /// Extra netlink header flags for the get, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateGetRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::GetSa(message));
         req.header.flags = NLM_F_REQUEST;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    /// If the state doesn't exist, the stream yields `Error::NotFound`.
+    /// Attributes unknown to this crate don't fail the message, see
+    /// `StateMessageExt::unknown_attributes`.
+    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
-        // A successful policy Get request returns with an Add/ModifyMessage response.
+        // A successful state Get request returns with an Add/ModifyMessage response.
+        // The kernel answers ENOENT if there is no such state, which is reported as
+        // Error::NotFound, and a trailing DONE message is skipped.
         match handle.request(req) {
-            Ok(response) => {
-                Either::Left(response.map(move |msg| Ok(try_xfrmnl!(msg, XfrmMessage::AddSa))))
-            }
+            Ok(response) => Either::Left(
+                response
+                    .filter(|msg| future::ready(!matches!(msg.payload, NetlinkPayload::Done(_))))
+                    .map(move |msg| {
+                        if let NetlinkPayload::Error(err) = &msg.payload {
+                            if err.raw_code() == -ENOENT {
+                                return Err(Error::NotFound);
+                            }
+                        }
+                        Ok(try_xfrmnl!(msg, XfrmMessage::AddSa))
+                    }),
+            ),
             Err(e) => Either::Right(future::err::<ModifyMessage, Error>(e).into_stream()),
         }
     }
 
+    /// Execute the request and return the state, or `None` if it doesn't exist.
+    pub async fn fetch(self) -> Result<Option<ModifyMessage>, Error> {
+        match self.execute().try_next().await {
+            Err(Error::NotFound) => Ok(None),
+            result => result,
+        }
+    }
+
     /// Return a mutable reference to the request message.
     pub fn message_mut(&mut self) -> &mut DelGetMessage {
         &mut self.message
     }
 }
 
+/// The identity of an xfrm state, as returned in a dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct SaKey {
+    pub src_addr: IpAddr,
+    pub dst_addr: IpAddr,
+    pub proto: u8,
+    pub spi: u32,
+}
+
+impl From<&ModifyMessage> for SaKey {
+    fn from(state: &ModifyMessage) -> Self {
+        let info = &state.user_sa_info;
+
+        SaKey {
+            src_addr: address_to_ip_lossy(&info.saddr, info.family),
+            dst_addr: address_to_ip_lossy(&info.id.daddr, info.family),
+            proto: info.id.proto,
+            spi: info.id.spi,
+        }
+    }
+}
+
 /// A request to dump xfrm states. This is equivalent to the `ip xfrm state list` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateGetDumpRequest {
     handle: Handle,
     message: GetDumpMessage,
+    netlink_flags: u16,
+    mark_filter: Option<(u32, u32)>,
 }
 
 impl StateGetDumpRequest {
     pub(crate) fn new(handle: Handle) -> Self {
         let message = GetDumpMessage::default();
 
-        StateGetDumpRequest { handle, message }
+        StateGetDumpRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+            mark_filter: None,
+        }
     }
 
     pub fn address_filter(
@@ -100,36 +178,178 @@ impl StateGetDumpRequest {
         dst_addr: IpAddr,
         dst_prefix_len: u8,
     ) -> Self {
-        let mut addr_filter = AddressFilter::default();
-
+        let addr_filter = self.address_filter_mut();
         addr_filter.source_prefix(&src_addr, src_prefix_len);
         addr_filter.destination_prefix(&dst_addr, dst_prefix_len);
+        self
+    }
+
+    /// Only dump the states to the `dst_addr/dst_prefix_len` subnet, whatever their source.
+    /// Can be combined with `src_filter`, and the kernel only returns states of the
+    /// same address family.
+    pub fn dst_filter(mut self, dst_addr: IpAddr, dst_prefix_len: u8) -> Self {
+        self.address_filter_mut()
+            .destination_prefix(&dst_addr, dst_prefix_len);
+        self
+    }
+
+    /// Only dump the states from the `src_addr/src_prefix_len` subnet, whatever their
+    /// destination. Can be combined with `dst_filter`.
+    pub fn src_filter(mut self, src_addr: IpAddr, src_prefix_len: u8) -> Self {
+        self.address_filter_mut()
+            .source_prefix(&src_addr, src_prefix_len);
+        self
+    }
+
+    // The kernel treats a prefix length of 0 as a wildcard, so a filter with
+    // only one side set matches any address on the other side. There is a single
+    // XFRMA_ADDRESS_FILTER attribute, shared by the filter methods.
+    fn address_filter_mut(&mut self) -> &mut AddressFilter {
+        let exists = self
+            .message
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, XfrmAttrs::AddressFilter(_)));
+        if !exists {
+            self.message
+                .nlas
+                .push(XfrmAttrs::AddressFilter(AddressFilter::default()));
+        }
 
         self.message
             .nlas
-            .push(XfrmAttrs::AddressFilter(addr_filter));
+            .iter_mut()
+            .find_map(|nla| match nla {
+                XfrmAttrs::AddressFilter(filter) => Some(filter),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Only return dumped states whose mark value, masked with `mask`, equals
+    /// `value & mask`. States without a mark are treated as having a mark of 0.
+    ///
+    /// The kernel can't filter state dumps on mark, so this is applied client-side
+    /// as the states are received: every state is still sent by the kernel.
+    pub fn mark_filter(mut self, value: u32, mask: u32) -> Self {
+        self.mark_filter = Some((value, mask));
         self
     }
 
-    /// Execute the request
-    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+    /// Extra netlink header flags for the dump, ORed with NLM_F_DUMP, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateGetDumpRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::GetDumpSa(message));
         req.header.flags = NLM_F_REQUEST | NLM_F_DUMP;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request.
+    /// Attributes unknown to this crate don't fail the message, see
+    /// `StateMessageExt::unknown_attributes`.
+    ///
+    /// The stream ends after the kernel's final DONE message, which the connection
+    /// consumes instead of forwarding. It also ends, without an error, if the
+    /// connection is closed in the middle of the dump, and the two cases can't be told
+    /// apart from the stream. A dump disturbed by concurrent changes fails with
+    /// `Error::DumpInterrupted`.
+    pub fn execute(self) -> impl TryStream<Ok = ModifyMessage, Error = Error> {
+        self.execute_with_progress(|_| ())
+    }
+
+    /// Execute the request, calling `on_progress` with the number of messages
+    /// received so far each time a message is received. States dropped by the
+    /// client-side `mark_filter` are counted as well.
+    ///
+    /// Netlink doesn't report how many entries a dump will return. To show progress
+    /// against a total, fetch the SA count with `get_sadinfo()` before starting the dump.
+    pub fn execute_with_progress<F>(
+        self,
+        mut on_progress: F,
+    ) -> impl TryStream<Ok = ModifyMessage, Error = Error>
+    where
+        F: FnMut(usize),
+    {
+        let mut handle = self.handle.clone();
+        let mark_filter = self.mark_filter;
+        let req = self.into_message();
+        let mut received = 0;
 
         // A successful state Get with dump flag request returns with an Add/ModifyMessage response.
+        // If the SAD changed during the dump, the kernel flags the responses as interrupted.
         match handle.request(req) {
-            Ok(response) => {
-                Either::Left(response.map(move |msg| Ok(try_xfrmnl!(msg, XfrmMessage::AddSa))))
-            }
+            Ok(response) => Either::Left(
+                response
+                    .map(move |msg| {
+                        received += 1;
+                        on_progress(received);
+
+                        if msg.header.flags & NLM_F_DUMP_INTR == NLM_F_DUMP_INTR {
+                            return Err(Error::DumpInterrupted);
+                        }
+                        Ok(try_xfrmnl!(msg, XfrmMessage::AddSa))
+                    })
+                    .try_filter(move |state| {
+                        let mark_ok = match mark_filter {
+                            Some((value, mask)) => {
+                                let state_mark = state
+                                    .nlas
+                                    .iter()
+                                    .find_map(|nla| match nla {
+                                        XfrmAttrs::Mark(mark) => Some(mark.value),
+                                        _ => None,
+                                    })
+                                    .unwrap_or(0);
+                                state_mark & mask == value & mask
+                            }
+                            None => true,
+                        };
+                        future::ready(mark_ok)
+                    }),
+            ),
             Err(e) => Either::Right(future::err::<ModifyMessage, Error>(e).into_stream()),
         }
     }
 
+    /// Execute the request, returning an iterator that blocks the current thread until
+    /// each state is received, for synchronous code. The states are received one at a
+    /// time as the iterator is advanced, they are not collected first.
+    ///
+    /// The `Connection` must be driven on another thread, e.g. spawned on a runtime
+    /// running in the background, otherwise the iterator blocks forever. Don't call
+    /// this from within an async task.
+    pub fn blocking_iter(self) -> impl Iterator<Item = Result<ModifyMessage, Error>> {
+        block_on_stream(Box::pin(self.execute().into_stream()))
+    }
+
+    /// Execute the request and collect all the states, or return the first error.
+    pub async fn list(self) -> Result<Vec<ModifyMessage>, Error> {
+        self.execute().try_collect().await
+    }
+
+    /// Execute the request and collect the states into a map keyed on their identity.
+    /// If the same state is returned more than once, the last one received is kept.
+    pub async fn collect_map(self) -> Result<HashMap<SaKey, ModifyMessage>, Error> {
+        self.execute()
+            .try_fold(HashMap::new(), |mut states, state| {
+                states.insert(SaKey::from(&state), state);
+                future::ready(Ok(states))
+            })
+            .await
+    }
+
     /// Return a mutable reference to the request message.
     pub fn message_mut(&mut self) -> &mut GetDumpMessage {
         &mut self.message
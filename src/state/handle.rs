@@ -1,13 +1,22 @@
 // SPDX-License-Identifier: MIT
 
+use futures::{future, stream::TryStreamExt};
 use std::net::IpAddr;
 
 use super::{
-    StateAllocSpiRequest, StateDeleteRequest, StateFlushRequest, StateGetDumpRequest,
-    StateGetRequest, StateGetSadInfoRequest, StateModifyRequest,
+    StateAllocSpiRequest, StateDeleteRequest, StateDeleteSummary, StateExpireRequest,
+    StateFlushRequest, StateGetAeRequest, StateGetDumpRequest, StateGetRequest,
+    StateGetSadInfoRequest, StateModifyRequest, StateSetAeRequest,
 };
-use crate::Handle;
+use crate::{address::check_families, Error, Handle, SaKey};
+use netlink_packet_xfrm::{state::ModifyMessage, UserSaId, XfrmAttrs};
 
+/// A handle for xfrm state (SA) requests, created with `Handle::state()`.
+///
+/// Mirrors `PolicyHandle`: `add`, `update`, `delete`, `get`, `get_dump`, `flush`, and the
+/// statistics request `get_sadinfo`. States can be deleted in bulk with `delete_where`.
+/// SPIs can be reserved with `alloc_spi`, and the async event state of an SA is
+/// available with `get_ae`/`set_ae`.
 #[non_exhaustive]
 pub struct StateHandle(Handle);
 
@@ -31,16 +40,101 @@ impl StateHandle {
         StateDeleteRequest::new(self.0.clone(), src_addr, dst_addr)
     }
 
+    /// Delete xfrm state identified by its SA id (destination address, SPI, protocol
+    /// and family), e.g. the `StateMessageExt::sa_id` of a dumped state.
+    ///
+    /// ESP, AH and COMP states are found by their id alone. States of other protocols
+    /// are also looked up by source address, which isn't part of the id, so use `delete`
+    /// for those. If the state has a mark, it must be set with `mark()` as well.
+    pub fn delete_id(&self, id: UserSaId) -> StateDeleteRequest {
+        StateDeleteRequest::from_id(self.0.clone(), id)
+    }
+
+    /// Delete all the xfrm states for which `predicate` returns true.
+    ///
+    /// The states are dumped first, then each matching state is deleted with its
+    /// addresses, protocol, SPI and mark. Failing to delete a state doesn't stop the
+    /// others from being deleted, the failures are returned in the summary. An error
+    /// is only returned if the dump itself fails, in which case nothing is deleted.
+    pub async fn delete_where<P>(&self, predicate: P) -> Result<StateDeleteSummary, Error>
+    where
+        P: Fn(&ModifyMessage) -> bool,
+    {
+        // Collect before deleting, deleting during the dump would interrupt it.
+        let states: Vec<ModifyMessage> = self
+            .get_dump()
+            .execute()
+            .try_filter(|state| future::ready(predicate(state)))
+            .try_collect()
+            .await?;
+
+        let mut summary = StateDeleteSummary::default();
+
+        for state in states {
+            let key = SaKey::from(&state);
+            let mut req = self
+                .delete(key.src_addr, key.dst_addr)
+                .protocol(key.proto)
+                .spi(key.spi);
+
+            for nla in &state.nlas {
+                if let XfrmAttrs::Mark(mark) = nla {
+                    req = req.mark(mark.value, mark.mask);
+                }
+            }
+
+            match req.execute().await {
+                Ok(()) => summary.deleted += 1,
+                Err(e) => summary.errors.push((key, e)),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Check whether a state exists, e.g. for idempotent provisioning. States with a
+    /// mark can't be found this way, use `get` with `mark()` and `fetch()` for those.
+    pub async fn exists(
+        &self,
+        src_addr: IpAddr,
+        dst_addr: IpAddr,
+        protocol: u8,
+        spi: u32,
+    ) -> Result<bool, Error> {
+        let state = self
+            .get(src_addr, dst_addr)
+            .protocol(protocol)
+            .spi(spi)
+            .fetch()
+            .await?;
+        Ok(state.is_some())
+    }
+
+    /// Force xfrm state to expire, either soft (rekey) or hard (delete)
+    pub fn expire(&self, src_addr: IpAddr, dst_addr: IpAddr) -> StateExpireRequest {
+        StateExpireRequest::new(self.0.clone(), src_addr, dst_addr)
+    }
+
     /// Flush xfrm state (equivalent to `ip xfrm state flush`)
     pub fn flush(&self) -> StateFlushRequest {
         StateFlushRequest::new(self.0.clone())
     }
 
+    /// Flush all the xfrm states, and return how many were removed.
+    /// See `StateFlushRequest::execute_counting`.
+    pub async fn flush_counting(&self) -> Result<u32, Error> {
+        self.flush().execute_counting().await
+    }
+
     /// Get xfrm state (equivalent to `ip xfrm state get`)
     pub fn get(&self, src_addr: IpAddr, dst_addr: IpAddr) -> StateGetRequest {
         StateGetRequest::new(self.0.clone(), src_addr, dst_addr)
     }
 
+    /// Get the async event state (replay counters, current lifetime) of an xfrm state
+    pub fn get_ae(&self, src_addr: IpAddr, dst_addr: IpAddr) -> StateGetAeRequest {
+        StateGetAeRequest::new(self.0.clone(), src_addr, dst_addr)
+    }
+
     /// Get (dump) all xfrm states (equivalent to `ip xfrm state list`)
     pub fn get_dump(&self) -> StateGetDumpRequest {
         StateGetDumpRequest::new(self.0.clone())
@@ -51,6 +145,36 @@ impl StateHandle {
         StateGetSadInfoRequest::new(self.0.clone())
     }
 
+    /// Set the async event state (replay counters, current lifetime) of an xfrm state
+    pub fn set_ae(&self, src_addr: IpAddr, dst_addr: IpAddr) -> StateSetAeRequest {
+        StateSetAeRequest::new(self.0.clone(), src_addr, dst_addr)
+    }
+
+    /// Same as `add`, but the addresses are checked to be of the same family
+    /// immediately instead of leaving it to the kernel.
+    pub fn try_add(&self, src_addr: IpAddr, dst_addr: IpAddr) -> Result<StateModifyRequest, Error> {
+        check_families(&src_addr, &dst_addr)?;
+        Ok(self.add(src_addr, dst_addr))
+    }
+
+    /// Same as `get`, but the addresses are checked to be of the same family
+    /// immediately instead of leaving it to the kernel.
+    pub fn try_get(&self, src_addr: IpAddr, dst_addr: IpAddr) -> Result<StateGetRequest, Error> {
+        check_families(&src_addr, &dst_addr)?;
+        Ok(self.get(src_addr, dst_addr))
+    }
+
+    /// Same as `update`, but the addresses are checked to be of the same family
+    /// immediately instead of leaving it to the kernel.
+    pub fn try_update(
+        &self,
+        src_addr: IpAddr,
+        dst_addr: IpAddr,
+    ) -> Result<StateModifyRequest, Error> {
+        check_families(&src_addr, &dst_addr)?;
+        Ok(self.update(src_addr, dst_addr))
+    }
+
     /// Update xfrm state (equivalent to `ip xfrm state update`)
     pub fn update(&self, src_addr: IpAddr, dst_addr: IpAddr) -> StateModifyRequest {
         StateModifyRequest::new(self.0.clone(), true, src_addr, dst_addr)
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{address::address_to_ip_lossy, EncapType, XfrmInterfaceId, XfrmMode};
+use netlink_packet_utils::nla::Nla;
+use netlink_packet_xfrm::{state::ModifyMessage, Mark, UserSaId, XfrmAttrs};
+
+/// Accessors for the attributes of states returned by get, dump and alloc spi requests.
+pub trait StateMessageExt {
+    /// The mode of the state.
+    fn mode(&self) -> XfrmMode;
+
+    /// Whether the state is in tunnel mode.
+    fn is_tunnel(&self) -> bool {
+        self.mode() == XfrmMode::Tunnel
+    }
+
+    /// The SA id of the state, i.e. its `user_sa_info.id` along with its family,
+    /// as taken by `StateHandle::delete_id`.
+    fn sa_id(&self) -> UserSaId;
+
+    /// The NAT-T encapsulation of the state, as (encap type, source port,
+    /// destination port, original address).
+    fn encap(&self) -> Option<(EncapType, u16, u16, IpAddr)>;
+
+    /// The xfrm interface the state is bound to, if any.
+    fn interface_id(&self) -> Option<XfrmInterfaceId>;
+
+    /// The mark (XFRMA_MARK) of the state, if it has one.
+    fn mark(&self) -> Option<Mark>;
+
+    /// When the state last passed traffic, in seconds since the UNIX epoch.
+    /// Only present once the state has been used.
+    fn last_used(&self) -> Option<u64>;
+
+    /// How long the state has been idle as of `now`, or `None` if it has never been used.
+    fn idle_for(&self, now: SystemTime) -> Option<Duration> {
+        let last_used = UNIX_EPOCH + Duration::from_secs(self.last_used()?);
+        Some(now.duration_since(last_used).unwrap_or_default())
+    }
+
+    /// The attributes that `netlink-packet-xfrm` doesn't know, e.g. ones added by a newer
+    /// kernel, as (attribute type, raw value). They are kept in `nlas` as
+    /// `XfrmAttrs::Other` rather than failing the whole message, so the known fields
+    /// of the state are still available.
+    fn unknown_attributes(&self) -> Vec<(u16, Vec<u8>)>;
+}
+
+impl StateMessageExt for ModifyMessage {
+    fn mode(&self) -> XfrmMode {
+        XfrmMode::from(self.user_sa_info.mode)
+    }
+
+    fn sa_id(&self) -> UserSaId {
+        let info = &self.user_sa_info;
+        UserSaId {
+            daddr: info.id.daddr,
+            spi: info.id.spi,
+            family: info.family,
+            proto: info.id.proto,
+        }
+    }
+
+    fn encap(&self) -> Option<(EncapType, u16, u16, IpAddr)> {
+        self.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::EncapsulationTemplate(tmpl) => Some((
+                EncapType::from(tmpl.encap_type),
+                tmpl.encap_sport,
+                tmpl.encap_dport,
+                address_to_ip_lossy(&tmpl.encap_oa, self.user_sa_info.family),
+            )),
+            _ => None,
+        })
+    }
+
+    fn interface_id(&self) -> Option<XfrmInterfaceId> {
+        self.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::IfId(ifid) => Some(XfrmInterfaceId(*ifid)),
+            _ => None,
+        })
+    }
+
+    fn mark(&self) -> Option<Mark> {
+        self.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::Mark(mark) => Some(Mark {
+                value: mark.value,
+                mask: mark.mask,
+            }),
+            _ => None,
+        })
+    }
+
+    fn last_used(&self) -> Option<u64> {
+        self.nlas.iter().find_map(|nla| match nla {
+            XfrmAttrs::LastUsed(last_used) => Some(*last_used),
+            _ => None,
+        })
+    }
+
+    fn unknown_attributes(&self) -> Vec<(u16, Vec<u8>)> {
+        self.nlas
+            .iter()
+            .filter_map(|nla| match nla {
+                XfrmAttrs::Other(other) => {
+                    let mut value = vec![0; other.value_len()];
+                    other.emit_value(&mut value);
+                    Some((other.kind(), value))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
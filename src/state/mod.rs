@@ -1,11 +1,17 @@
 // SPDX-License-Identifier: MIT
 
+mod aevent;
+pub use self::aevent::*;
+
 mod allocspi;
 pub use self::allocspi::*;
 
 mod delete;
 pub use self::delete::*;
 
+mod expire;
+pub use self::expire::*;
+
 mod flush;
 pub use self::flush::*;
 
@@ -15,8 +21,14 @@ pub use self::get::*;
 mod handle;
 pub use self::handle::*;
 
+mod message;
+pub use self::message::*;
+
 mod modify;
 pub use self::modify::*;
 
 mod sadinfo;
 pub use self::sadinfo::*;
+
+#[cfg(test)]
+mod test;
@@ -4,20 +4,38 @@ use futures::stream::StreamExt;
 use std::ffi::CString;
 use std::net::IpAddr;
 
-use crate::{try_nl, Error, Handle};
+use crate::{
+    address::{check_address_not_mapped, check_selector},
+    aead_icv_lengths, alg_key_lengths, auth_trunc_len,
+    constants::XFRMA_SA_DIR,
+    iptfs::check_iptfs_direction,
+    lifetime::lifetime_current_nla,
+    link::ifindex_by_name,
+    parse_hex_key, try_nl, EncapType, Error, Handle, IpTfsConfig, KernelFeature, LifetimeConfig,
+    SaDirection, SelectorBuilder, XfrmInterfaceId, XfrmMode,
+};
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
+use netlink_packet_utils::nla::DefaultNla;
 use netlink_packet_xfrm::{
     constants::*, state::ModifyMessage, Address, Alg, AlgAead, AlgAuth, EncapTmpl, Mark, Replay,
-    ReplayEsn, SecurityCtx, UserOffloadDev, XfrmAttrs, XfrmMessage, XFRM_ALG_AEAD_NAME_LEN,
-    XFRM_ALG_AUTH_NAME_LEN, XFRM_ALG_NAME_LEN,
+    ReplayEsn, SecurityCtx, Selector, UserOffloadDev, UserPolicyInfo, UserTemplate, XfrmAttrs,
+    XfrmMessage, XFRM_ALG_AEAD_NAME_LEN, XFRM_ALG_AUTH_NAME_LEN, XFRM_ALG_NAME_LEN,
 };
 
+const IPPROTO_ESP: u8 = 50;
+const IPPROTO_AH: u8 = 51;
+const IPPROTO_COMP: u8 = 108;
+
 /// A request to add or update xfrm state. This is equivalent to the `ip xfrm state add|update` commands.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateModifyRequest {
     handle: Handle,
     message: ModifyMessage,
+    netlink_flags: u16,
     update: bool,
+    allow_no_crypto: bool,
+    anti_replay_disabled: bool,
 }
 
 impl StateModifyRequest {
@@ -30,7 +48,10 @@ impl StateModifyRequest {
         StateModifyRequest {
             handle,
             message,
+            netlink_flags: 0,
             update,
+            allow_no_crypto: false,
+            anti_replay_disabled: false,
         }
     }
 
@@ -43,7 +64,24 @@ impl StateModifyRequest {
         self
     }
 
-    pub fn authentication(mut self, alg_name: &str, key: &Vec<u8>) -> Result<Self, Error> {
+    /// Set the authentication algorithm, with the standard truncation length of the
+    /// algorithm (`auth_trunc_len`) as an XFRMA_ALG_AUTH_TRUNC attribute, e.g. 128 bits
+    /// for hmac(sha256) as in RFC 4868. Unknown algorithms are sent without a truncation
+    /// length, like `auth_full` does.
+    pub fn authentication(self, alg_name: &str, key: &[u8]) -> Result<Self, Error> {
+        match auth_trunc_len(alg_name) {
+            Some(trunc_len) => self.authentication_trunc(alg_name, key, trunc_len),
+            None => self.auth_full(alg_name, key),
+        }
+    }
+
+    /// Set the authentication algorithm as a legacy XFRMA_ALG_AUTH attribute, without
+    /// a truncation length (equivalent to `auth` in `ip xfrm state add`). The kernel
+    /// then uses its own default truncation for the algorithm, which for hmac(sha256)
+    /// is the pre-RFC 4868 96 bits. Only use it to interoperate with old peers.
+    pub fn auth_full(mut self, alg_name: &str, key: &[u8]) -> Result<Self, Error> {
+        check_key_length(alg_name, key)?;
+
         let mut auth_name: [u8; XFRM_ALG_NAME_LEN] = [0; XFRM_ALG_NAME_LEN];
         let mut c_auth_name = CString::new(alg_name)
             .map_err(|_| Error::AlgName(alg_name.to_string()))?
@@ -58,7 +96,7 @@ impl StateModifyRequest {
         let alg_auth = Alg {
             alg_name: auth_name,
             alg_key_len: (key.len() * 8) as u32,
-            alg_key: key.clone(),
+            alg_key: key.to_vec(),
         };
 
         self.message
@@ -67,12 +105,20 @@ impl StateModifyRequest {
         Ok(self)
     }
 
+    /// Same as `authentication`, but the key is given as a hex string (e.g. `0x0123abcd`).
+    pub fn authentication_hex(self, alg_name: &str, hex_key: &str) -> Result<Self, Error> {
+        let key = parse_hex_key(hex_key)?;
+        self.authentication(alg_name, &key)
+    }
+
     pub fn authentication_trunc(
         mut self,
         alg_name: &str,
-        key: &Vec<u8>,
+        key: &[u8],
         trunc_len: u32,
     ) -> Result<Self, Error> {
+        check_key_length(alg_name, key)?;
+
         let mut auth_name: [u8; XFRM_ALG_AUTH_NAME_LEN] = [0; XFRM_ALG_AUTH_NAME_LEN];
         let mut c_auth_name = CString::new(alg_name)
             .map_err(|_| Error::AlgName(alg_name.to_string()))?
@@ -88,7 +134,7 @@ impl StateModifyRequest {
             alg_name: auth_name,
             alg_key_len: (key.len() * 8) as u32,
             alg_trunc_len: trunc_len,
-            alg_key: key.clone(),
+            alg_key: key.to_vec(),
         };
 
         self.message
@@ -119,7 +165,9 @@ impl StateModifyRequest {
         Ok(self)
     }
 
-    pub fn encryption(mut self, alg_name: &str, key: &Vec<u8>) -> Result<Self, Error> {
+    pub fn encryption(mut self, alg_name: &str, key: &[u8]) -> Result<Self, Error> {
+        check_key_length(alg_name, key)?;
+
         let mut enc_name: [u8; XFRM_ALG_NAME_LEN] = [0; XFRM_ALG_NAME_LEN];
         let mut c_enc_name = CString::new(alg_name)
             .map_err(|_| Error::AlgName(alg_name.to_string()))?
@@ -134,20 +182,31 @@ impl StateModifyRequest {
         let alg_enc = Alg {
             alg_name: enc_name,
             alg_key_len: (key.len() * 8) as u32,
-            alg_key: key.clone(),
+            alg_key: key.to_vec(),
         };
 
         self.message.nlas.push(XfrmAttrs::EncryptionAlg(alg_enc));
         Ok(self)
     }
 
-    // icv_len should be in bits
+    /// Same as `encryption`, but the key is given as a hex string (e.g. `0x0123abcd`).
+    pub fn encryption_hex(self, alg_name: &str, hex_key: &str) -> Result<Self, Error> {
+        let key = parse_hex_key(hex_key)?;
+        self.encryption(alg_name, &key)
+    }
+
+    // icv_len should be in bits. The key must include the salt appended by IKE
+    // (e.g. 16 + 4 bytes for AES-128-GCM). A wrong key or ICV length would give
+    // an SA that drops every packet, so both are checked for the known algorithms.
     pub fn encryption_aead(
         mut self,
         alg_name: &str,
-        key: &Vec<u8>,
+        key: &[u8],
         icv_len: u32,
     ) -> Result<Self, Error> {
+        check_key_length(alg_name, key)?;
+        check_icv_length(alg_name, icv_len)?;
+
         let mut enc_name: [u8; XFRM_ALG_AEAD_NAME_LEN] = [0; XFRM_ALG_AEAD_NAME_LEN];
         let mut c_enc_name = CString::new(alg_name)
             .map_err(|_| Error::AlgName(alg_name.to_string()))?
@@ -163,7 +222,7 @@ impl StateModifyRequest {
             alg_name: enc_name,
             alg_key_len: (key.len() * 8) as u32,
             alg_icv_len: icv_len,
-            alg_key: key.clone(),
+            alg_key: key.to_vec(),
         };
 
         self.message
@@ -176,6 +235,29 @@ impl StateModifyRequest {
         self.message.user_sa_info.mode = mode;
         self
     }
+    /// Use IP-TFS/AGGFRAG (RFC 9347), aggregating and fragmenting the inner packets
+    /// into outer ESP packets, e.g. for constant-rate tunnels. This sets the mode to
+    /// `XfrmMode::IpTfs` and the XFRMA_IPTFS_* parameters.
+    ///
+    /// The state also needs a direction, see `sa_direction`, which must match the
+    /// parameters of `config`: `execute()` fails with `Error::MissingSaDirection` or
+    /// `Error::IpTfsDirection` otherwise. Requires Linux 6.14, `execute()` fails with
+    /// `Error::IpTfsUnsupported` on older kernels.
+    pub fn iptfs(mut self, config: IpTfsConfig) -> Self {
+        self.message.user_sa_info.mode = XfrmMode::IpTfs.into();
+        self.message.nlas.extend(config.nlas());
+        self
+    }
+    /// Set the direction of the state (XFRMA_SA_DIR, Linux 6.10), required by IP-TFS.
+    /// The kernel then rejects parameters that don't apply to that direction, e.g. a
+    /// replay window on an outbound state.
+    pub fn sa_direction(mut self, direction: SaDirection) -> Self {
+        self.message.nlas.push(XfrmAttrs::Other(DefaultNla::new(
+            XFRMA_SA_DIR,
+            vec![direction.into()],
+        )));
+        self
+    }
     pub fn reqid(mut self, reqid: u32) -> Self {
         self.message.user_sa_info.reqid = reqid;
         self
@@ -191,6 +273,19 @@ impl StateModifyRequest {
         self.message.user_sa_info.flags = flags;
         self
     }
+    // Override the family inferred from the addresses given when the request was created.
+    // Accepts an AddressFamily or a raw AF_* value.
+    pub fn family(mut self, family: impl Into<u16>) -> Self {
+        self.message.user_sa_info.family = family.into();
+        self
+    }
+    // The selector family is inferred from the selector addresses (see selector_addresses),
+    // independently of the state family. Set it to AF_UNSPEC (0), along with the
+    // XFRM_STATE_AF_UNSPEC flag, for a tunnel mode state carrying both IPv4 and IPv6.
+    pub fn selector_family(mut self, family: impl Into<u16>) -> Self {
+        self.message.user_sa_info.selector.family = family.into();
+        self
+    }
     pub fn extra_flags(mut self, flags: u32) -> Self {
         self.message.nlas.push(XfrmAttrs::ExtraFlags(flags));
         self
@@ -202,10 +297,23 @@ impl StateModifyRequest {
         self.message.nlas.push(XfrmAttrs::SecurityContext(sc));
         self
     }
+    /// Set the security context from an SELinux label, e.g.
+    /// `system_u:object_r:ipsec_spd_t:s0`. Like iproute2 (`ctx`), the context is sent
+    /// with a trailing NUL, which is how the kernel LSMs expect it.
+    pub fn security_context_label(self, label: &str) -> Self {
+        let mut secctx = Vec::with_capacity(label.len() + 1);
+        secctx.extend_from_slice(label.as_bytes());
+        secctx.push(0);
+        self.security_context(&secctx)
+    }
     pub fn ifid(mut self, ifid: u32) -> Self {
         self.message.nlas.push(XfrmAttrs::IfId(ifid));
         self
     }
+    /// Same as `ifid`, with a typed xfrm interface id.
+    pub fn interface_id(self, ifid: XfrmInterfaceId) -> Self {
+        self.ifid(ifid.0)
+    }
     pub fn mark(mut self, mark: u32, mask: u32) -> Self {
         self.message
             .nlas
@@ -235,10 +343,11 @@ impl StateModifyRequest {
         offload_seq: u32,
         offload_seq_hi: u32,
     ) -> Self {
+        self.anti_replay_disabled = false;
         if (size > u32::BITS)
             || (self.message.user_sa_info.flags & XFRM_STATE_ESN) == XFRM_STATE_ESN
         {
-            let bmp_len: u32 = (size + u32::BITS - 1) / u32::BITS;
+            let bmp_len: u32 = size.div_ceil(u32::BITS);
             let replay_esn = ReplayEsn {
                 bmp_len,
                 oseq: offload_seq,
@@ -265,6 +374,21 @@ impl StateModifyRequest {
         self
     }
 
+    /// Explicitly disable anti-replay protection by setting a replay window of 0,
+    /// rather than relying on the default.
+    ///
+    /// Without a replay window, captured packets can be replayed to the receiver and
+    /// will be accepted. Only do this when replay protection is provided elsewhere, or
+    /// for outbound SAs. ESN needs a replay window to track the high order bits of the
+    /// sequence number, so `execute()` fails with `Error::InvalidReplayWindow` if the
+    /// state also uses ESN, whichever was set first. A later `replay_window()` call
+    /// enables anti-replay again.
+    pub fn disable_anti_replay(mut self) -> Self {
+        self.anti_replay_disabled = true;
+        self.message.user_sa_info.replay_window = 0;
+        self
+    }
+
     pub fn time_limit(mut self, soft: u64, hard: u64) -> Self {
         self.message
             .user_sa_info
@@ -297,68 +421,107 @@ impl StateModifyRequest {
         self.message.user_sa_info.lifetime_cfg.hard_packet_limit = hard;
         self
     }
-    pub fn selector_addresses(
+    /// Set all the limits at once, replacing the ones set by `time_limit`,
+    /// `time_use_limit`, `byte_limit` and `packet_limit`.
+    pub fn lifetimes(mut self, lifetimes: LifetimeConfig) -> Self {
+        lifetimes.apply(&mut self.message.user_sa_info.lifetime_cfg);
+        self
+    }
+    // Restore the current lifetime of a state, e.g. when importing it on a standby
+    // node, so that it expires and rekeys on schedule instead of starting from zero.
+    // The kernel ignores the current lifetime in xfrm_usersa_info, so this is sent
+    // as an XFRMA_LTIME_VAL attribute. Times are in seconds since the UNIX epoch.
+    pub fn lifetime_current(
         mut self,
+        bytes: u64,
+        packets: u64,
+        add_time: u64,
+        use_time: u64,
+    ) -> Self {
+        self.message
+            .nlas
+            .push(lifetime_current_nla(bytes, packets, add_time, use_time));
+        self
+    }
+    /// Replace the whole selector, e.g. with one built with `SelectorBuilder`.
+    pub fn selector(mut self, selector: impl Into<Selector>) -> Self {
+        self.message.user_sa_info.selector = selector.into();
+        self
+    }
+    /// Bind a selector to the state (`xfrm_usersa_info.sel`). Transport mode states can
+    /// use it to restrict the traffic they accept, and BEET mode states need it to carry
+    /// the inner addresses. Use the other `selector_*` methods for protocol and ports.
+    ///
+    /// The selector family follows these addresses, and doesn't have to be the family
+    /// of the state addresses: a tunnel mode state between IPv6 endpoints can carry
+    /// IPv4 traffic with an IPv4 selector, and the other way around. The message then
+    /// carries the outer family in `xfrm_usersa_info.family` and the inner one in
+    /// `sel.family`. The kernel rejects mismatched families in the other modes.
+    pub fn selector_addresses(
+        self,
         src_addr: IpAddr,
         src_prefix_len: u8,
         dst_addr: IpAddr,
         dst_prefix_len: u8,
     ) -> Self {
-        self.message
-            .user_sa_info
-            .selector
-            .source_prefix(&src_addr, src_prefix_len);
-        self.message
-            .user_sa_info
-            .selector
-            .destination_prefix(&dst_addr, dst_prefix_len);
-        self
+        self.update_selector(|sel| {
+            sel.addresses(src_addr, src_prefix_len, dst_addr, dst_prefix_len)
+        })
     }
-    pub fn selector_protocol(mut self, proto: u8) -> Self {
-        self.message.user_sa_info.selector.proto = proto;
-        self
+    pub fn selector_protocol(self, proto: u8) -> Self {
+        self.update_selector(|sel| sel.protocol(proto))
     }
-    pub fn selector_protocol_src_port(mut self, port: u16) -> Self {
-        self.message.user_sa_info.selector.sport = port;
-        self.message.user_sa_info.selector.sport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_src_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port(port))
     }
-    pub fn selector_protocol_dst_port(mut self, port: u16) -> Self {
-        self.message.user_sa_info.selector.dport = port;
-        self.message.user_sa_info.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_dst_port(self, port: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port(port))
     }
-    pub fn selector_protocol_type(mut self, proto_type: u8) -> Self {
-        self.message.user_sa_info.selector.sport = proto_type as u16;
-        self.message.user_sa_info.selector.sport_mask = u16::MAX;
-        self
+    // See SelectorBuilder::protocol_src_port_masked for which ranges can be matched.
+    pub fn selector_protocol_src_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_src_port_masked(port, mask))
     }
-    pub fn selector_protocol_code(mut self, proto_code: u8) -> Self {
-        self.message.user_sa_info.selector.dport = proto_code as u16;
-        self.message.user_sa_info.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_dst_port_masked(self, port: u16, mask: u16) -> Self {
+        self.update_selector(|sel| sel.protocol_dst_port_masked(port, mask))
     }
-    pub fn selector_protocol_gre_key(mut self, gre_key: u32) -> Self {
-        self.message.user_sa_info.selector.sport = (gre_key >> 16) as u16;
-        self.message.user_sa_info.selector.sport_mask = u16::MAX;
-        self.message.user_sa_info.selector.dport = (gre_key & 0xffff) as u16;
-        self.message.user_sa_info.selector.dport_mask = u16::MAX;
-        self
+    pub fn selector_protocol_type(self, proto_type: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_type(proto_type))
+    }
+    pub fn selector_protocol_code(self, proto_code: u8) -> Self {
+        self.update_selector(|sel| sel.protocol_code(proto_code))
+    }
+    pub fn selector_protocol_gre_key(self, gre_key: u32) -> Self {
+        self.update_selector(|sel| sel.protocol_gre_key(gre_key))
     }
-    pub fn selector_dev_id(mut self, id: u32) -> Self {
-        self.message.user_sa_info.selector.ifindex = id as i32;
+    pub fn selector_dev_id(self, id: u32) -> Self {
+        self.update_selector(|sel| sel.dev_id(id))
+    }
+    /// Same as `selector_dev_id`, looking up the index of the named interface.
+    /// Fails with `Error::InterfaceNotFound` if there is no such interface, rather
+    /// than leaving an ifindex of 0, which would match any interface.
+    pub fn selector_dev(self, name: &str) -> Result<Self, Error> {
+        Ok(self.selector_dev_id(ifindex_by_name(name)?))
+    }
+    fn update_selector<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(SelectorBuilder) -> SelectorBuilder,
+    {
+        self.message.user_sa_info.selector = f(self.message.user_sa_info.selector.into()).build();
         self
     }
 
+    // encap_type can be an EncapType or a raw UDP_ENCAP_*/TCP_ENCAP_* value.
+    // For ESP-in-TCP (EncapType::EspInTcp) the ports are those of the TCP
+    // connection, e.g. 4500 or 443, see EncapType for the kernel requirements.
     pub fn encapsulation(
         mut self,
-        encap_type: u16,
+        encap_type: impl Into<u16>,
         src_port: u16,
         dst_port: u16,
         outside_addr: IpAddr,
     ) -> Self {
         let encap_tmpl = EncapTmpl {
-            encap_type,
+            encap_type: encap_type.into(),
             encap_sport: src_port,
             encap_dport: dst_port,
             encap_oa: Address::from_ip(&outside_addr),
@@ -389,12 +552,75 @@ impl StateModifyRequest {
         self
     }
 
-    /// Execute the request.
-    pub async fn execute(self) -> Result<(), Error> {
+    /// Embed a policy (and its templates) in the state, for per-SA/socket policies.
+    /// Only valid for transport mode states, so `mode()` must be set to XFRM_MODE_TRANSPORT first.
+    pub fn attach_policy(
+        mut self,
+        info: UserPolicyInfo,
+        templates: Vec<UserTemplate>,
+    ) -> Result<Self, Error> {
+        if self.message.user_sa_info.mode != XFRM_MODE_TRANSPORT {
+            return Err(Error::InvalidMode(self.message.user_sa_info.mode));
+        }
+
+        self.message.nlas.push(XfrmAttrs::PolicyInfo(info));
+        if !templates.is_empty() {
+            self.message.nlas.push(XfrmAttrs::Template(templates));
+        }
+        Ok(self)
+    }
+
+    /// Build the outbound and inbound states of a tunnel from this request, which should
+    /// be set up with the local address as source and the remote address as destination.
+    ///
+    /// Returns `(outbound, inbound)`: the outbound state is this request with `spi_out`,
+    /// the inbound state is a copy with the source and destination swapped (including
+    /// the selector and the encapsulation ports) and `spi_in`. Everything else, such as
+    /// the keys, algorithms, mode and reqid, is shared by both.
+    pub fn mirror(self, spi_in: u32, spi_out: u32) -> (Self, Self) {
+        let mut inbound = self.clone();
+        let info = &mut inbound.message.user_sa_info;
+
+        std::mem::swap(&mut info.saddr, &mut info.id.daddr);
+        info.id.spi = spi_in;
+
+        let sel = &mut info.selector;
+        std::mem::swap(&mut sel.saddr, &mut sel.daddr);
+        std::mem::swap(&mut sel.prefixlen_s, &mut sel.prefixlen_d);
+        std::mem::swap(&mut sel.sport, &mut sel.dport);
+        std::mem::swap(&mut sel.sport_mask, &mut sel.dport_mask);
+
+        for nla in inbound.message.nlas.iter_mut() {
+            if let XfrmAttrs::EncapsulationTemplate(encap) = nla {
+                std::mem::swap(&mut encap.encap_sport, &mut encap.encap_dport);
+            }
+        }
+
+        (self.spi(spi_out), inbound)
+    }
+
+    /// Don't fail with `Error::MissingAlgorithm` when an ESP state has no algorithm
+    /// (or an AH state no authentication algorithm, or a COMP state no compression
+    /// algorithm), e.g. in tests. Such a state is accepted by the kernel but drops all
+    /// of its traffic.
+    pub fn allow_no_crypto(mut self) -> Self {
+        self.allow_no_crypto = true;
+        self
+    }
+
+    /// Extra netlink header flags for the add or update, e.g. NLM_F_REPLACE, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateModifyRequest {
-            mut handle,
             message,
+            netlink_flags,
             update,
+            ..
         } = self;
 
         let mut req = if update {
@@ -403,8 +629,39 @@ impl StateModifyRequest {
             NetlinkMessage::from(XfrmMessage::AddSa(message))
         };
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
 
-        let mut response = handle.request(req)?;
+    /// Execute the request.
+    ///
+    /// If the state uses ESP-in-TCP encapsulation, `Error::EncapUnsupported` is returned
+    /// without sending the request when the kernel doesn't support it, see
+    /// `KernelFeature::TcpEncap`. Errors from the kernel are returned unchanged.
+    ///
+    /// Fails with `Error::MissingAlgorithm` if no algorithm is set, see `allow_no_crypto`,
+    /// with `Error::UnexpectedAlgorithm` if an AH state has an encryption algorithm,
+    /// and with `Error::IpTfsUnsupported` if IP-TFS is used and the kernel is too old.
+    pub async fn execute(self) -> Result<(), Error> {
+        self.check_addresses()?;
+        self.check_algorithms()?;
+        self.check_replay()?;
+        self.check_iptfs()?;
+
+        if self.is_iptfs() && !self.handle.kernel_supports(KernelFeature::IpTfs).await {
+            return Err(Error::IpTfsUnsupported);
+        }
+
+        let tcp_encap = u16::from(EncapType::EspInTcp);
+        let is_tcp_encap = self.message.nlas.iter().any(|nla| {
+            matches!(nla, XfrmAttrs::EncapsulationTemplate(tmpl) if tmpl.encap_type == tcp_encap)
+        });
+        if is_tcp_encap && !self.handle.kernel_supports(KernelFeature::TcpEncap).await {
+            return Err(Error::EncapUnsupported(tcp_encap));
+        }
+
+        let mut handle = self.handle.clone();
+        let mut response = handle.request(self.into_message())?;
 
         while let Some(message) = response.next().await {
             try_nl!(message);
@@ -413,19 +670,16 @@ impl StateModifyRequest {
     }
 
     /// Execute the request without waiting for an ACK response.
+    /// The request is only queued on the connection when this returns, see `Handle::notify`.
     pub fn execute_noack(self) -> Result<(), Error> {
-        let StateModifyRequest {
-            mut handle,
-            message,
-            update,
-        } = self;
+        self.check_addresses()?;
+        self.check_algorithms()?;
+        self.check_replay()?;
+        self.check_iptfs()?;
 
-        let mut req = if update {
-            NetlinkMessage::from(XfrmMessage::UpdateSa(message))
-        } else {
-            NetlinkMessage::from(XfrmMessage::AddSa(message))
-        };
-        req.header.flags = NLM_F_REQUEST;
+        let mut handle = self.handle.clone();
+        let mut req = self.into_message();
+        req.header.flags &= !NLM_F_ACK;
 
         let mut _response = handle.request(req)?;
 
@@ -436,4 +690,104 @@ impl StateModifyRequest {
     pub fn message_mut(&mut self) -> &mut ModifyMessage {
         &mut self.message
     }
+
+    fn check_addresses(&self) -> Result<(), Error> {
+        let info = &self.message.user_sa_info;
+        check_address_not_mapped(&info.saddr, info.family)?;
+        check_address_not_mapped(&info.id.daddr, info.family)?;
+        check_selector(&info.selector)
+    }
+
+    // Anti-replay can't be disabled for an ESN state.
+    fn check_replay(&self) -> Result<(), Error> {
+        let esn = (self.message.user_sa_info.flags & XFRM_STATE_ESN) == XFRM_STATE_ESN
+            || self
+                .message
+                .nlas
+                .iter()
+                .any(|nla| matches!(nla, XfrmAttrs::ReplayStateEsn(_)));
+        if self.anti_replay_disabled && esn {
+            return Err(Error::InvalidReplayWindow(0));
+        }
+        Ok(())
+    }
+
+    fn is_iptfs(&self) -> bool {
+        self.message.user_sa_info.mode == u8::from(XfrmMode::IpTfs)
+    }
+
+    fn check_iptfs(&self) -> Result<(), Error> {
+        if self.is_iptfs() {
+            check_iptfs_direction(&self.message.nlas)?;
+        }
+        Ok(())
+    }
+
+    // ESP states need an encryption, authentication or AEAD algorithm, AH states an
+    // authentication algorithm and COMP states a compression algorithm. Other
+    // protocols have none. AH only authenticates, an encryption algorithm is a mistake
+    // even with allow_no_crypto.
+    fn check_algorithms(&self) -> Result<(), Error> {
+        let proto = self.message.user_sa_info.id.proto;
+
+        let has = |matches: fn(&XfrmAttrs) -> bool| self.message.nlas.iter().any(matches);
+        let encryption = |nla: &XfrmAttrs| {
+            matches!(
+                nla,
+                XfrmAttrs::EncryptionAlg(_) | XfrmAttrs::EncryptionAlgAead(_)
+            )
+        };
+        let authentication = |nla: &XfrmAttrs| {
+            matches!(
+                nla,
+                XfrmAttrs::AuthenticationAlg(_) | XfrmAttrs::AuthenticationAlgTrunc(_)
+            )
+        };
+        let compression = |nla: &XfrmAttrs| matches!(nla, XfrmAttrs::CompressionAlg(_));
+
+        if proto == IPPROTO_AH && has(encryption) {
+            return Err(Error::UnexpectedAlgorithm(proto));
+        }
+        if self.allow_no_crypto {
+            return Ok(());
+        }
+
+        let ok = match proto {
+            IPPROTO_ESP => has(encryption) || has(authentication),
+            IPPROTO_AH => has(authentication),
+            IPPROTO_COMP => has(compression),
+            _ => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::MissingAlgorithm(proto))
+        }
+    }
+}
+
+// Reject keys whose length doesn't match a known algorithm,
+// unknown algorithm names are left for the kernel to validate.
+fn check_key_length(alg_name: &str, key: &[u8]) -> Result<(), Error> {
+    let got = (key.len() * 8) as u32;
+
+    match alg_key_lengths(alg_name) {
+        Some(expected) if !expected.contains(&got) => Err(Error::InvalidKeyLength {
+            alg: alg_name.to_string(),
+            expected,
+            got,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn check_icv_length(alg_name: &str, icv_len: u32) -> Result<(), Error> {
+    match aead_icv_lengths(alg_name) {
+        Some(expected) if !expected.contains(&icv_len) => Err(Error::InvalidIcvLength {
+            alg: alg_name.to_string(),
+            expected,
+            got: icv_len,
+        }),
+        _ => Ok(()),
+    }
 }
@@ -5,34 +5,54 @@ use futures::stream::StreamExt;
 use crate::{try_xfrmnl, Error, Handle};
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
 use netlink_packet_xfrm::{
-    state::{GetSadInfoMessage, NewSadInfoMessage},
+    state::{GetSadInfoMessage, NewSadInfoMessage, SadInfoAttrs},
     XfrmMessage,
 };
 
 /// A request to get xfrm state statistics. This is equivalent to the `ip xfrm state count` command.
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct StateGetSadInfoRequest {
     handle: Handle,
     message: GetSadInfoMessage,
+    netlink_flags: u16,
 }
 
 impl StateGetSadInfoRequest {
     pub(crate) fn new(handle: Handle) -> Self {
         let message = GetSadInfoMessage { flags: u32::MAX };
 
-        StateGetSadInfoRequest { handle, message }
+        StateGetSadInfoRequest {
+            handle,
+            message,
+            netlink_flags: 0,
+        }
     }
 
-    /// Execute the request
-    pub async fn execute(self) -> Result<NewSadInfoMessage, Error> {
+    /// Extra netlink header flags for the SAD info get, see `Handle`.
+    pub fn netlink_flags(mut self, flags: u16) -> Self {
+        self.netlink_flags |= flags;
+        self
+    }
+
+    /// Return the netlink message that `execute()` would send, without sending it.
+    pub fn into_message(self) -> NetlinkMessage<XfrmMessage> {
         let StateGetSadInfoRequest {
-            mut handle,
             message,
+            netlink_flags,
+            ..
         } = self;
 
         let mut req = NetlinkMessage::from(XfrmMessage::GetSadInfo(message));
-
         req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.header.flags |= netlink_flags;
+        req
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<NewSadInfoMessage, Error> {
+        let mut handle = self.handle.clone();
+        let req = self.into_message();
 
         let mut response = handle.request(req)?;
 
@@ -47,3 +67,38 @@ impl StateGetSadInfoRequest {
         &mut self.message
     }
 }
+
+/// Accessors for the statistics returned by a `get_sadinfo` request.
+pub trait SadInfoMessageExt {
+    /// The number of states in the SAD.
+    fn count(&self) -> Option<u32>;
+
+    /// The current number of buckets in the SAD hash tables.
+    fn hash_count(&self) -> Option<u32>;
+
+    /// The maximum number of buckets the SAD hash tables can grow to.
+    fn hash_max(&self) -> Option<u32>;
+}
+
+impl SadInfoMessageExt for NewSadInfoMessage {
+    fn count(&self) -> Option<u32> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SadInfoAttrs::SadCount(count) => Some(*count),
+            _ => None,
+        })
+    }
+
+    fn hash_count(&self) -> Option<u32> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SadInfoAttrs::SadHInfo(hinfo) => Some(hinfo.sadhcnt),
+            _ => None,
+        })
+    }
+
+    fn hash_max(&self) -> Option<u32> {
+        self.nlas.iter().find_map(|nla| match nla {
+            SadInfoAttrs::SadHInfo(hinfo) => Some(hinfo.sadhmcnt),
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,1114 @@
+// SPDX-License-Identifier: MIT
+
+use futures::stream::{StreamExt, TryStreamExt};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    decode_message, encode_message, mock_handle, EncapType, Error, IpTfsConfig, KernelFeature,
+    MockController, SaDirection, SadInfoMessageExt, SelectorBuilder, SpiRange, StateMessageExt,
+    XfrmInterfaceId, XfrmMode,
+};
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_REQUEST};
+use netlink_packet_utils::nla::NlasIterator;
+use netlink_packet_xfrm::{
+    monitor::NewAsyncEventMessage, state::ModifyMessage, Address, AddressFilter, EncapTmpl,
+    UserPolicyInfo, UserTemplate, XfrmAttrs, XfrmMessage, AF_INET, AF_INET6, IPPROTO_AH,
+    IPPROTO_COMP, IPPROTO_ESP, IPPROTO_TCP, XFRMA_ALG_AUTH, XFRMA_ALG_AUTH_TRUNC, XFRMA_IF_ID,
+    XFRMA_POLICY, XFRMA_TMPL, XFRM_AE_ETHR, XFRM_AE_RTHR, XFRM_MODE_BEET, XFRM_MODE_TRANSPORT,
+    XFRM_MODE_TUNNEL, XFRM_MSG_GETAE, XFRM_POLICY_OUT, XFRM_STATE_ESN,
+};
+
+const SRC: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+const DST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+const EPERM: i32 = 1;
+const EINVAL: i32 = 22;
+
+#[tokio::test]
+async fn flush_reports_the_errno() {
+    let (handle, mock) = mock_handle();
+    mock.respond(|_| vec![MockController::error(EPERM)]);
+
+    let result = handle.state().flush().execute().await;
+
+    assert_eq!(result.unwrap_err().errno(), Some(EPERM));
+    assert_eq!(mock.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn add_sends_the_state() {
+    let (handle, mock) = mock_handle();
+
+    handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .execute()
+        .await
+        .unwrap();
+
+    let sent = mock.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].header.flags, NLM_F_REQUEST | NLM_F_ACK);
+    match &sent[0].payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddSa(msg)) => {
+            assert_eq!(msg.user_sa_info.id.spi, 0x100);
+            assert_eq!(msg.user_sa_info.id.proto, IPPROTO_ESP);
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn add_with_tcp_encap_unsupported() {
+    let (handle, mock) = mock_handle();
+    handle
+        .features
+        .lock()
+        .unwrap()
+        .insert(KernelFeature::TcpEncap, false);
+
+    let result = handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .encapsulation(EncapType::EspInTcp, 4500, 4500, SRC)
+        .execute()
+        .await;
+
+    match result {
+        Err(Error::EncapUnsupported(7)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(mock.sent().is_empty());
+}
+
+#[tokio::test]
+async fn add_with_tcp_encap_passes_einval_through() {
+    let (handle, mock) = mock_handle();
+    handle
+        .features
+        .lock()
+        .unwrap()
+        .insert(KernelFeature::TcpEncap, true);
+    mock.respond(|_| vec![MockController::error(EINVAL)]);
+
+    let result = handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .encapsulation(EncapType::EspInTcp, 4500, 4500, SRC)
+        .execute()
+        .await;
+
+    assert_eq!(result.unwrap_err().errno(), Some(EINVAL));
+    assert_eq!(mock.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn dump_yields_every_state() {
+    let (handle, mock) = mock_handle();
+    mock.respond(|_| {
+        (1..=2)
+            .map(|spi| {
+                let mut msg = ModifyMessage::default();
+                msg.user_sa_info.id.spi = spi;
+                NetlinkMessage::from(XfrmMessage::AddSa(msg))
+            })
+            .collect()
+    });
+
+    let states: Vec<ModifyMessage> = handle
+        .state()
+        .get_dump()
+        .execute()
+        .try_collect()
+        .await
+        .unwrap();
+
+    let spis: Vec<u32> = states.iter().map(|s| s.user_sa_info.id.spi).collect();
+    assert_eq!(spis, vec![1, 2]);
+}
+
+#[tokio::test]
+async fn attach_policy_serializes_policy_and_templates() {
+    let (handle, _) = mock_handle();
+
+    let info = UserPolicyInfo {
+        direction: XFRM_POLICY_OUT,
+        index: 42,
+        ..Default::default()
+    };
+    let mut tmpl = UserTemplate::default();
+    tmpl.id.proto = IPPROTO_ESP;
+
+    let mut message = handle
+        .state()
+        .add(SRC, DST)
+        .mode(XFRM_MODE_TRANSPORT)
+        .attach_policy(info, vec![tmpl, tmpl])
+        .unwrap()
+        .into_message();
+    let buf = encode_message(&mut message);
+
+    // The attributes follow the netlink header (16 bytes) and xfrm_usersa_info (224 bytes).
+    let attrs: Vec<(u16, usize)> = NlasIterator::new(&buf[16 + 224..])
+        .map(|nla| {
+            let nla = nla.unwrap();
+            (nla.kind(), nla.value().len())
+        })
+        .collect();
+    assert_eq!(attrs, vec![(XFRMA_POLICY, 168), (XFRMA_TMPL, 2 * 64)]);
+
+    match decode_message(&buf).unwrap().payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddSa(decoded)) => match message.payload {
+            NetlinkPayload::InnerMessage(XfrmMessage::AddSa(sent)) => {
+                assert_eq!(decoded.nlas, sent.nlas)
+            }
+            payload => panic!("unexpected payload {:?}", payload),
+        },
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn add_beet_state_with_inner_selector() {
+    let (handle, mock) = mock_handle();
+    let inner_src = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    let inner_dst = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1));
+
+    handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .mode(XFRM_MODE_BEET)
+        .selector(SelectorBuilder::new(inner_src, 32, inner_dst, 32))
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .execute()
+        .await
+        .unwrap();
+
+    match &mock.sent()[0].payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddSa(msg)) => {
+            let info = &msg.user_sa_info;
+            assert_eq!(info.mode, XFRM_MODE_BEET);
+            assert_eq!(info.saddr, Address::from_ip(&SRC));
+            assert_eq!(info.selector.saddr, Address::from_ip(&inner_src));
+            assert_eq!(info.selector.daddr, Address::from_ip(&inner_dst));
+            assert_eq!(info.selector.prefixlen_s, 32);
+            assert_eq!(info.selector.prefixlen_d, 32);
+            assert_eq!(info.selector.family, 2);
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn disable_anti_replay_rejects_esn_in_any_order() {
+    let (handle, mock) = mock_handle();
+    let add = || {
+        handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x100)
+            .encryption("cbc(aes)", &[0; 16])
+            .unwrap()
+    };
+
+    let before = add().disable_anti_replay().flags(XFRM_STATE_ESN).execute();
+    let after = add().flags(XFRM_STATE_ESN).disable_anti_replay().execute();
+
+    for result in [before.await, after.await].iter() {
+        assert!(
+            matches!(result, Err(Error::InvalidReplayWindow(0))),
+            "{:?}",
+            result
+        );
+    }
+    assert!(mock.sent().is_empty());
+}
+
+#[tokio::test]
+async fn request_raw_yields_every_response() {
+    let (mut handle, mock) = mock_handle();
+    mock.respond(|_| vec![MockController::error(EPERM)]);
+
+    let request = handle.state().flush().into_message();
+    let responses: Vec<_> = handle.request_raw(request).unwrap().collect().await;
+
+    assert_eq!(responses.len(), 1);
+    match &responses[0].payload {
+        NetlinkPayload::Error(err) => assert_eq!(err.raw_code(), -EPERM),
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+// The XFRMA_SA_DIR and XFRMA_IPTFS_* attributes of an inbound and an outbound IP-TFS
+// state, following xfrm_usersa_info. The integers are in host byte order.
+#[cfg(target_endian = "little")]
+#[tokio::test]
+async fn iptfs_attributes_serialize() {
+    let (handle, _) = mock_handle();
+    let add = |direction, config| {
+        let mut message = handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x100)
+            .sa_direction(direction)
+            .iptfs(config)
+            .into_message();
+        encode_message(&mut message)[16 + 224..].to_vec()
+    };
+
+    let inbound = add(
+        SaDirection::In,
+        IpTfsConfig::new()
+            .drop_time(Duration::from_millis(1))
+            .reorder_window(3),
+    );
+    #[rustfmt::skip]
+    assert_eq!(
+        inbound,
+        [
+            5, 0, 33, 0, 1, 0, 0, 0,
+            8, 0, 36, 0, 0xe8, 0x03, 0, 0,
+            6, 0, 37, 0, 3, 0, 0, 0,
+        ]
+    );
+
+    let outbound = add(
+        SaDirection::Out,
+        IpTfsConfig::new()
+            .dont_frag()
+            .init_delay(Duration::from_micros(200))
+            .max_queue_size(0x10000)
+            .packet_size(1400),
+    );
+    #[rustfmt::skip]
+    assert_eq!(
+        outbound,
+        [
+            5, 0, 33, 0, 2, 0, 0, 0,
+            4, 0, 38, 0,
+            8, 0, 39, 0, 200, 0, 0, 0,
+            8, 0, 40, 0, 0, 0, 1, 0,
+            8, 0, 41, 0, 0x78, 0x05, 0, 0,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn iptfs_checks_the_direction() {
+    let (handle, mock) = mock_handle();
+    let add = || {
+        handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x100)
+            .encryption("cbc(aes)", &[0; 16])
+            .unwrap()
+    };
+
+    let missing = add().iptfs(IpTfsConfig::new()).execute().await;
+    assert!(
+        matches!(missing, Err(Error::MissingSaDirection)),
+        "{:?}",
+        missing
+    );
+
+    let outbound = add()
+        .sa_direction(SaDirection::Out)
+        .iptfs(IpTfsConfig::new().packet_size(1400).reorder_window(3))
+        .execute()
+        .await;
+    assert!(
+        matches!(
+            outbound,
+            Err(Error::IpTfsDirection("reorder_window", SaDirection::Out))
+        ),
+        "{:?}",
+        outbound
+    );
+
+    let inbound = add()
+        .sa_direction(SaDirection::In)
+        .iptfs(IpTfsConfig::new().dont_frag())
+        .execute_noack();
+    assert!(
+        matches!(
+            inbound,
+            Err(Error::IpTfsDirection("dont_frag", SaDirection::In))
+        ),
+        "{:?}",
+        inbound
+    );
+    assert!(mock.sent().is_empty());
+}
+
+#[test]
+fn encap_accessor_is_typed() {
+    let mut msg = ModifyMessage::default();
+    msg.user_sa_info.family = 2;
+    msg.nlas.push(XfrmAttrs::EncapsulationTemplate(EncapTmpl {
+        encap_type: 7,
+        encap_sport: 4500,
+        encap_dport: 443,
+        encap_oa: Address::from_ip(&SRC),
+    }));
+
+    assert_eq!(msg.encap(), Some((EncapType::EspInTcp, 4500, 443, SRC)));
+
+    msg.nlas = vec![XfrmAttrs::EncapsulationTemplate(EncapTmpl {
+        encap_type: 9,
+        ..Default::default()
+    })];
+    assert_eq!(msg.encap().unwrap().0, EncapType::Unknown(9));
+}
+
+fn sa(message: NetlinkMessage<XfrmMessage>) -> ModifyMessage {
+    match message.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::AddSa(msg)) => msg,
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn mirror_swaps_the_directions() {
+    let (handle, _) = mock_handle();
+    let local_net = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0));
+    let remote_net = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0));
+
+    let (outbound, inbound) = handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .mode(XFRM_MODE_TUNNEL)
+        .reqid(7)
+        .selector_addresses(local_net, 24, remote_net, 16)
+        .selector_protocol(IPPROTO_TCP)
+        .selector_protocol_src_port_masked(1024, 0xfc00)
+        .selector_protocol_dst_port(443)
+        .encapsulation(EncapType::EspInUdp, 4500, 4501, SRC)
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .mirror(0x200, 0x100);
+    let outbound = sa(outbound.into_message());
+    let inbound = sa(inbound.into_message());
+
+    let info = &outbound.user_sa_info;
+    assert_eq!(info.id.spi, 0x100);
+    assert_eq!(info.saddr, Address::from_ip(&SRC));
+    assert_eq!(info.id.daddr, Address::from_ip(&DST));
+    assert_eq!(info.selector.saddr, Address::from_ip(&local_net));
+    assert_eq!(info.selector.daddr, Address::from_ip(&remote_net));
+    assert_eq!(
+        outbound.encap(),
+        Some((EncapType::EspInUdp, 4500, 4501, SRC))
+    );
+
+    let info = &inbound.user_sa_info;
+    assert_eq!(info.id.spi, 0x200);
+    assert_eq!(info.saddr, Address::from_ip(&DST));
+    assert_eq!(info.id.daddr, Address::from_ip(&SRC));
+
+    let sel = &info.selector;
+    assert_eq!(sel.saddr, Address::from_ip(&remote_net));
+    assert_eq!(sel.daddr, Address::from_ip(&local_net));
+    assert_eq!((sel.prefixlen_s, sel.prefixlen_d), (16, 24));
+    assert_eq!((sel.sport, sel.sport_mask), (443, 0xffff));
+    assert_eq!((sel.dport, sel.dport_mask), (1024, 0xfc00));
+    assert_eq!(sel.proto, IPPROTO_TCP);
+
+    // The original address is the peer's address before NAT, it isn't swapped.
+    assert_eq!(
+        inbound.encap(),
+        Some((EncapType::EspInUdp, 4501, 4500, SRC))
+    );
+
+    // Everything else is shared.
+    assert_eq!(info.reqid, 7);
+    assert_eq!(info.mode, XFRM_MODE_TUNNEL);
+    assert_eq!(info.id.proto, IPPROTO_ESP);
+    assert_eq!(
+        inbound
+            .nlas
+            .iter()
+            .find(|nla| matches!(nla, XfrmAttrs::EncryptionAlg(_))),
+        outbound
+            .nlas
+            .iter()
+            .find(|nla| matches!(nla, XfrmAttrs::EncryptionAlg(_)))
+    );
+}
+
+#[tokio::test]
+async fn auth_variants_serialize() {
+    let (handle, _) = mock_handle();
+    let key = [0; 32];
+    let auth_attribute = |mut message: NetlinkMessage<XfrmMessage>| {
+        let buf = encode_message(&mut message);
+        let nla = NlasIterator::new(&buf[16 + 224..]).next().unwrap().unwrap();
+        (nla.kind(), nla.value().to_vec())
+    };
+    let add = || {
+        handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x100)
+    };
+
+    // xfrm_algo_auth: the name, the key length and the truncation length in bits.
+    let (kind, value) = auth_attribute(
+        add()
+            .authentication("hmac(sha256)", &key)
+            .unwrap()
+            .into_message(),
+    );
+    assert_eq!(kind, XFRMA_ALG_AUTH_TRUNC);
+    assert_eq!(&value[..13], b"hmac(sha256)\0");
+    assert_eq!(value[64..68], 256u32.to_ne_bytes());
+    assert_eq!(value[68..72], 128u32.to_ne_bytes());
+    assert_eq!(value.len(), 72 + 32);
+
+    // xfrm_algo: the name and the key length, without a truncation length.
+    let (kind, value) = auth_attribute(
+        add()
+            .auth_full("hmac(sha256)", &key)
+            .unwrap()
+            .into_message(),
+    );
+    assert_eq!(kind, XFRMA_ALG_AUTH);
+    assert_eq!(&value[..13], b"hmac(sha256)\0");
+    assert_eq!(value[64..68], 256u32.to_ne_bytes());
+    assert_eq!(value.len(), 68 + 32);
+}
+
+fn address_filters(message: NetlinkMessage<XfrmMessage>) -> Vec<AddressFilter> {
+    match message.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::GetDumpSa(msg)) => msg
+            .nlas
+            .into_iter()
+            .filter_map(|nla| match nla {
+                XfrmAttrs::AddressFilter(filter) => Some(filter),
+                _ => None,
+            })
+            .collect(),
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn dst_filter_matches_any_source() {
+    let (handle, mock) = mock_handle();
+    let peer_net = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 0));
+
+    let filters = address_filters(
+        handle
+            .state()
+            .get_dump()
+            .dst_filter(peer_net, 24)
+            .into_message(),
+    );
+    assert_eq!(filters.len(), 1);
+    assert_eq!(filters[0].daddr, Address::from_ip(&peer_net));
+    assert_eq!(filters[0].dplen, 24);
+    assert_eq!(filters[0].saddr, Address::default());
+    assert_eq!(filters[0].splen, 0);
+    assert_eq!(filters[0].family, 2);
+
+    // The kernel applies the filter, every state it returns is yielded.
+    mock.respond(|_| {
+        [SRC, DST]
+            .iter()
+            .map(|src| {
+                let mut msg = ModifyMessage::default();
+                msg.user_sa_info.source(src);
+                msg.user_sa_info
+                    .destination(&IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)));
+                NetlinkMessage::from(XfrmMessage::AddSa(msg))
+            })
+            .collect()
+    });
+    let states: Vec<ModifyMessage> = handle
+        .state()
+        .get_dump()
+        .dst_filter(peer_net, 24)
+        .execute()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(states.len(), 2);
+}
+
+#[tokio::test]
+async fn address_filters_share_one_attribute() {
+    let (handle, _) = mock_handle();
+    let peer_net = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 0));
+
+    let message = handle
+        .state()
+        .get_dump()
+        .dst_filter(peer_net, 24)
+        .src_filter(SRC, 32)
+        .into_message();
+    let filters = address_filters(message);
+    assert_eq!(filters.len(), 1);
+    assert_eq!((filters[0].splen, filters[0].dplen), (32, 24));
+
+    let message = handle
+        .state()
+        .get_dump()
+        .dst_filter(peer_net, 24)
+        .address_filter(SRC, 32, DST, 32)
+        .into_message();
+    let filters = address_filters(message);
+    assert_eq!(filters.len(), 1);
+    assert_eq!(filters[0].saddr, Address::from_ip(&SRC));
+    assert_eq!(filters[0].daddr, Address::from_ip(&DST));
+    assert_eq!((filters[0].splen, filters[0].dplen), (32, 32));
+}
+
+#[tokio::test]
+async fn aead_aes_gcm_with_salt() {
+    let (handle, _) = mock_handle();
+    let add = || {
+        handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x100)
+    };
+
+    for aes_bytes in [16, 24, 32] {
+        let key = vec![0; aes_bytes + 4];
+        let message = add()
+            .encryption_aead("rfc4106(gcm(aes))", &key, 128)
+            .unwrap()
+            .into_message();
+
+        match sa(message).nlas.as_slice() {
+            [XfrmAttrs::EncryptionAlgAead(alg)] => {
+                assert_eq!(alg.alg_key_len, (aes_bytes as u32 + 4) * 8);
+                assert_eq!(alg.alg_icv_len, 128);
+            }
+            nlas => panic!("unexpected attributes {:?}", nlas),
+        }
+    }
+
+    // The key without its salt.
+    let result = add().encryption_aead("rfc4106(gcm(aes))", &[0; 16], 128);
+    assert!(
+        matches!(result, Err(Error::InvalidKeyLength { got: 128, .. })),
+        "{:?}",
+        result.map(|_| ())
+    );
+
+    let result = add().encryption_aead("rfc4106(gcm(aes))", &[0; 20], 112);
+    assert!(
+        matches!(result, Err(Error::InvalidIcvLength { got: 112, .. })),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
+
+#[tokio::test]
+async fn key_length_checked_for_ciphers_only() {
+    let (handle, _) = mock_handle();
+    let add = || {
+        handle
+            .state()
+            .add(SRC, DST)
+            .protocol(IPPROTO_ESP)
+            .spi(0x100)
+    };
+
+    // HMAC keys shorter or longer than the hash are valid.
+    for key_len in [16, 20, 64] {
+        assert!(add()
+            .authentication("hmac(sha256)", &vec![0; key_len])
+            .is_ok());
+    }
+
+    let result = add().encryption("cbc(aes)", &[0; 20]);
+    assert!(
+        matches!(result, Err(Error::InvalidKeyLength { got: 160, .. })),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
+
+#[tokio::test]
+async fn alloc_spi_checks_the_range() {
+    let (handle, mock) = mock_handle();
+
+    let result = handle
+        .state()
+        .alloc_spi(SRC, DST)
+        .protocol(IPPROTO_COMP)
+        .spi_range(0x100, 0x10000)
+        .allocate()
+        .await;
+    assert_eq!(result, Err(Error::InvalidCpi(0x10000)));
+
+    // The protocol may be set after the range.
+    let range = SpiRange::new(0x100, 0x10000).unwrap();
+    let result = handle
+        .state()
+        .alloc_spi(SRC, DST)
+        .range(range)
+        .protocol(IPPROTO_COMP)
+        .allocate()
+        .await;
+    assert_eq!(result, Err(Error::InvalidCpi(0x10000)));
+
+    let result = handle
+        .state()
+        .alloc_spi(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi_range(0x100, 0xff)
+        .allocate()
+        .await;
+    assert_eq!(result, Err(Error::InvalidSpiRange(0x100, 0xff)));
+
+    let result = handle
+        .state()
+        .alloc_spi(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(255);
+    assert_eq!(result.unwrap_err(), Error::InvalidSpi(255));
+
+    assert!(mock.sent().is_empty());
+}
+
+#[cfg(target_endian = "little")]
+fn decode_sa(lines: &[&str]) -> ModifyMessage {
+    let buf = hex::decode(lines.concat()).unwrap();
+    sa(decode_message(&buf).unwrap())
+}
+
+// An XFRM_MSG_NEWSA for an esp (spi 0x1000) tunnel mode state 192.0.2.1 -> 192.0.2.2
+// with reqid 1, as returned by a get, last used at 1700000000 (2023-11-14 22:13:20 UTC).
+#[cfg(target_endian = "little")]
+const STATE_LAST_USED: &[&str] = &[
+    // nlmsghdr
+    "fc000000100000000000000000000000",
+    // xfrm_usersa_info: selector
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000000000000000000000000000000000000000000",
+    // id, saddr
+    "c00002020000000000000000000000000010000032000000",
+    "c0000201000000000000000000000000",
+    // lifetime_cfg
+    "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    // lifetime_cur, stats
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000000000000000000",
+    // seq, reqid, family, mode, replay_window, flags
+    "0000000001000000020001000000000000000000",
+    // XFRMA_LASTUSED
+    "0c000f0000f1536500000000",
+];
+
+#[cfg(target_endian = "little")]
+#[test]
+fn last_used_of_a_returned_state() {
+    let state = decode_sa(STATE_LAST_USED);
+    assert_eq!(state.last_used(), Some(1_700_000_000));
+
+    let last_used = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(
+        state.idle_for(last_used + Duration::from_secs(90)),
+        Some(Duration::from_secs(90))
+    );
+    // A clock behind the kernel's doesn't make the state idle for a negative time.
+    assert_eq!(
+        state.idle_for(last_used - Duration::from_secs(1)),
+        Some(Duration::ZERO)
+    );
+
+    let unused = ModifyMessage::default();
+    assert_eq!(unused.last_used(), None);
+    assert_eq!(unused.idle_for(SystemTime::now()), None);
+}
+
+// An XFRM_MSG_NEWSADINFO for 12 states, with 16 hash buckets out of at most 1048576.
+#[cfg(target_endian = "little")]
+const SADINFO: &[&str] = &[
+    // nlmsghdr, flags
+    "28000000220000000000000000000000",
+    "00000000",
+    // XFRMA_SAD_CNT
+    "080001000c000000",
+    // XFRMA_SAD_HINFO
+    "0c0002001000000000001000",
+];
+
+#[cfg(target_endian = "little")]
+#[test]
+fn sadinfo_accessors() {
+    let buf = hex::decode(SADINFO.concat()).unwrap();
+    let info = match decode_message(&buf).unwrap().payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::NewSadInfo(info)) => info,
+        payload => panic!("unexpected payload {:?}", payload),
+    };
+
+    assert_eq!(info.count(), Some(12));
+    assert_eq!(info.hash_count(), Some(16));
+    assert_eq!(info.hash_max(), Some(1_048_576));
+}
+
+// Offset of the mode in an XFRM_MSG_NEWSA, after the header and the fields of
+// xfrm_usersa_info that precede it.
+#[cfg(target_endian = "little")]
+const STATE_MODE: usize = 230;
+
+#[cfg(target_endian = "little")]
+#[test]
+fn mode_of_a_returned_state() {
+    let modes = [
+        (0, XfrmMode::Transport),
+        (1, XfrmMode::Tunnel),
+        (2, XfrmMode::RouteOptimization),
+        (3, XfrmMode::InTrigger),
+        (4, XfrmMode::Beet),
+        (5, XfrmMode::IpTfs),
+        (42, XfrmMode::Unknown(42)),
+    ];
+    let mut buf = hex::decode(STATE_LAST_USED.concat()).unwrap();
+    assert_eq!(buf[STATE_MODE], 1);
+
+    for (value, mode) in modes {
+        buf[STATE_MODE] = value;
+        let state = sa(decode_message(&buf).unwrap());
+        assert_eq!(state.mode(), mode);
+        assert_eq!(state.is_tunnel(), mode == XfrmMode::Tunnel);
+    }
+}
+
+// The same state as STATE_LAST_USED in transport mode, with mark 0x2a/0xff.
+#[cfg(target_endian = "little")]
+const STATE_WITH_MARK: &[&str] = &[
+    // nlmsghdr
+    "fc000000100000000000000000000000",
+    // xfrm_usersa_info: selector
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000000000000000000000000000000000000000000",
+    // id, saddr
+    "c00002020000000000000000000000000010000032000000",
+    "c0000201000000000000000000000000",
+    // lifetime_cfg
+    "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    // lifetime_cur, stats
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000000000000000000",
+    // seq, reqid, family, mode, replay_window, flags
+    "0000000001000000020000000000000000000000",
+    // XFRMA_MARK
+    "0c0015002a000000ff000000",
+];
+
+#[cfg(target_endian = "little")]
+#[tokio::test]
+async fn mark_of_a_returned_state() {
+    let state = decode_sa(STATE_WITH_MARK);
+    let mark = state.mark().unwrap();
+    assert_eq!((mark.value, mark.mask), (0x2a, 0xff));
+    assert_eq!(state.mode(), XfrmMode::Transport);
+
+    assert!(decode_sa(STATE_LAST_USED).mark().is_none());
+
+    // The mark set by the builder reads back the same.
+    let (handle, _) = mock_handle();
+    let mut message = handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x1000)
+        .mark(0x2a, 0xff)
+        .into_message();
+    let state = sa(decode_message(&encode_message(&mut message)).unwrap());
+    let mark = state.mark().unwrap();
+    assert_eq!((mark.value, mark.mask), (0x2a, 0xff));
+}
+
+// STATE_LAST_USED with an attribute of type 99 that netlink-packet-xfrm doesn't know,
+// as a newer kernel may return.
+#[cfg(target_endian = "little")]
+const STATE_WITH_UNKNOWN_ATTRIBUTE: &[&str] = &[
+    // nlmsghdr
+    "08010000100000000000000000000000",
+    // xfrm_usersa_info: selector
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000000000000000000000000000000000000000000",
+    // id, saddr
+    "c00002020000000000000000000000000010000032000000",
+    "c0000201000000000000000000000000",
+    // lifetime_cfg
+    "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    // lifetime_cur, stats
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000000000000000000",
+    // seq, reqid, family, mode, replay_window, flags
+    "0000000001000000020001000000000000000000",
+    // unknown attribute 99
+    "0c006300deadbeef01020304",
+    // XFRMA_LASTUSED
+    "0c000f0000f1536500000000",
+];
+
+#[cfg(target_endian = "little")]
+#[tokio::test]
+async fn unknown_attribute_is_kept_raw() {
+    let state = decode_sa(STATE_WITH_UNKNOWN_ATTRIBUTE);
+    assert_eq!(state.user_sa_info.reqid, 1);
+    assert_eq!(state.mode(), XfrmMode::Tunnel);
+    assert_eq!(state.last_used(), Some(1_700_000_000));
+    assert_eq!(
+        state.unknown_attributes(),
+        vec![(99, vec![0xde, 0xad, 0xbe, 0xef, 1, 2, 3, 4])]
+    );
+
+    // Nor does it fail a dump.
+    let (handle, mock) = mock_handle();
+    mock.respond(|_| {
+        let buf = hex::decode(STATE_WITH_UNKNOWN_ATTRIBUTE.concat()).unwrap();
+        vec![decode_message(&buf).unwrap(), decode_message(&buf).unwrap()]
+    });
+
+    let states: Vec<ModifyMessage> = handle
+        .state()
+        .get_dump()
+        .execute()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(states.len(), 2);
+    for state in states {
+        assert_eq!(state.last_used(), Some(1_700_000_000));
+        assert_eq!(state.unknown_attributes().len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn interface_id_serializes() {
+    let (handle, _) = mock_handle();
+    let mut message = handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .mode(XFRM_MODE_TUNNEL)
+        .interface_id(XfrmInterfaceId(7))
+        .into_message();
+    let buf = encode_message(&mut message);
+
+    let nla = NlasIterator::new(&buf[16 + 224..]).next().unwrap().unwrap();
+    assert_eq!(nla.kind(), XFRMA_IF_ID);
+    assert_eq!(nla.value(), 7u32.to_ne_bytes());
+
+    let state = sa(decode_message(&buf).unwrap());
+    assert_eq!(state.interface_id(), Some(XfrmInterfaceId(7)));
+}
+
+#[tokio::test]
+async fn ipv4_selector_between_ipv6_endpoints() {
+    let (handle, _) = mock_handle();
+    let src = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+    let dst = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+    let inner_src = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0));
+    let inner_dst = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0));
+    let mut message = handle
+        .state()
+        .add(src, dst)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .mode(XFRM_MODE_TUNNEL)
+        .selector_addresses(inner_src, 24, inner_dst, 24)
+        .into_message();
+
+    let state = sa(decode_message(&encode_message(&mut message)).unwrap());
+    let info = &state.user_sa_info;
+    assert_eq!(info.family, AF_INET6);
+    assert_eq!(info.saddr, Address::from_ip(&src));
+    assert_eq!(info.id.daddr, Address::from_ip(&dst));
+    assert_eq!(info.selector.family, AF_INET);
+    assert_eq!(info.selector.saddr, Address::from_ip(&inner_src));
+    assert_eq!(info.selector.daddr, Address::from_ip(&inner_dst));
+    assert_eq!(
+        (info.selector.prefixlen_s, info.selector.prefixlen_d),
+        (24, 24)
+    );
+}
+
+#[tokio::test]
+async fn delete_id_of_a_dumped_state() {
+    let (handle, mock) = mock_handle();
+    mock.respond(|req| match req.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::DeleteSa(_)) => vec![MockController::ack()],
+        _ => [0x100, 0x200]
+            .iter()
+            .map(|&spi| {
+                let mut msg = ModifyMessage::default();
+                msg.user_sa_info.source(&SRC);
+                msg.user_sa_info.destination(&DST);
+                msg.user_sa_info.id.proto = IPPROTO_ESP;
+                msg.user_sa_info.id.spi = spi;
+                NetlinkMessage::from(XfrmMessage::AddSa(msg))
+            })
+            .collect(),
+    });
+
+    let states: Vec<ModifyMessage> = handle
+        .state()
+        .get_dump()
+        .execute()
+        .try_collect()
+        .await
+        .unwrap();
+    let id = states[1].sa_id();
+    handle.state().delete_id(id).execute().await.unwrap();
+
+    let sent = mock.sent();
+    assert_eq!(sent.len(), 2);
+    match &sent[1].payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::DeleteSa(msg)) => {
+            assert_eq!(msg.user_sa_id, id);
+            assert_eq!(msg.user_sa_id.spi, 0x200);
+            assert_eq!(msg.user_sa_id.daddr, Address::from_ip(&DST));
+            assert_eq!(msg.user_sa_id.family, AF_INET);
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+}
+
+#[tokio::test]
+async fn ae_thresholds_round_trip() {
+    let (handle, mock) = mock_handle();
+    // Store the thresholds of NEWAE requests and return them on GETAE, like the kernel.
+    let mut stored = Vec::new();
+    mock.respond(move |req| match &req.payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::NewAsyncEvent(msg)) => {
+            stored = msg.nlas.clone();
+            vec![MockController::ack()]
+        }
+        payload if payload.message_type() == XFRM_MSG_GETAE => {
+            let msg = NewAsyncEventMessage {
+                nlas: stored.clone(),
+                ..Default::default()
+            };
+            vec![NetlinkMessage::from(XfrmMessage::NewAsyncEvent(msg))]
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    });
+
+    handle
+        .state()
+        .set_ae(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .replay_thresh(32)
+        .etimer_thresh(10)
+        .execute()
+        .await
+        .unwrap();
+
+    let sent = mock.sent();
+    match &sent[0].payload {
+        NetlinkPayload::InnerMessage(XfrmMessage::NewAsyncEvent(msg)) => {
+            assert_eq!(msg.id.flags, XFRM_AE_RTHR | XFRM_AE_ETHR);
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+
+    let ae = handle
+        .state()
+        .get_ae(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .execute()
+        .await
+        .unwrap();
+    let thresholds: Vec<&XfrmAttrs> = ae
+        .nlas
+        .iter()
+        .filter(|nla| {
+            matches!(
+                nla,
+                XfrmAttrs::ReplayThreshold(_) | XfrmAttrs::EventTimeThreshold(_)
+            )
+        })
+        .collect();
+    assert_eq!(
+        thresholds,
+        [
+            &XfrmAttrs::ReplayThreshold(32),
+            &XfrmAttrs::EventTimeThreshold(10)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ah_needs_an_authentication_algorithm() {
+    let (handle, mock) = mock_handle();
+    let ah = || handle.state().add(SRC, DST).protocol(IPPROTO_AH).spi(0x100);
+
+    let result = ah()
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .execute()
+        .await;
+    assert_eq!(result, Err(Error::UnexpectedAlgorithm(IPPROTO_AH)));
+
+    let result = ah()
+        .authentication("hmac(sha256)", &[0; 32])
+        .unwrap()
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .allow_no_crypto()
+        .execute()
+        .await;
+    assert_eq!(result, Err(Error::UnexpectedAlgorithm(IPPROTO_AH)));
+
+    let result = ah().execute().await;
+    assert_eq!(result, Err(Error::MissingAlgorithm(IPPROTO_AH)));
+    assert!(mock.sent().is_empty());
+
+    ah().authentication("hmac(sha256)", &[0; 32])
+        .unwrap()
+        .execute()
+        .await
+        .unwrap();
+    ah().allow_no_crypto().execute().await.unwrap();
+    assert_eq!(mock.sent().len(), 2);
+
+    // ESP can do with encryption only.
+    handle
+        .state()
+        .add(SRC, DST)
+        .protocol(IPPROTO_ESP)
+        .spi(0x100)
+        .encryption("cbc(aes)", &[0; 16])
+        .unwrap()
+        .execute()
+        .await
+        .unwrap();
+}